@@ -1,131 +1,312 @@
 use std::{error::Error, fs, io::Write};
+use std::path::PathBuf;
 use chrono::Local;
-use crate::filesystem::Dir;
+use crate::filesystem::{Dir, Snapshot};
 use crate::model::Promt;
-use crate::coderun::parser::{parse_and_execute, CMD};
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize)]
-struct GptRequest {
-    model: String,
-    messages: Vec<GptMessage>,
-}
-
-#[derive(Serialize, Clone)]
-struct GptMessage {
-   pub role: String,
-   pub content: String,
-}
-
-#[derive(Deserialize)]
-struct GptResponse {
-    choices: Vec<GptChoice>,
-}
-
-#[derive(Deserialize)]
-struct GptChoice {
-    message: GptChoiceMessage,
-}
-
-#[derive(Deserialize)]
-struct GptChoiceMessage {
-    content: String,
-}
+use crate::coderun::parser::{parse_and_execute, ExecOptions, Status, CMD};
+use crate::coderun::diff::HunkResult;
+use crate::coderun::workspace::DryRun;
+use crate::filesystem::PatchOutcome;
+use crate::provider::{Message, ModelProvider, Usage};
+use crate::git::Checkpoints;
+use crate::error::OrchestraError;
+use crate::context::ContextBudget;
+use crate::session::{Session, SessionState};
+use crate::board::BoardEntry;
+use crate::config::Employee;
+use crate::report::Report;
 
 pub struct Agent {
+    pub name: String,
     pub promt: Promt,
+    /// Dir of the employee, holds its log, report and sessions dirs
+    pub home: PathBuf,
     pub current_script: usize,
-    pub log_path: String,
-    pub conversation_history: Vec<GptMessage>,
+    pub log_path: PathBuf,
+    pub conversation_history: Vec<Message>,
+    pub provider: Box<dyn ModelProvider>,
+    pub options: ExecOptions,
+    /// Branch which gets a commit per script, None outside a git repository
+    pub checkpoints: Option<Checkpoints>,
+    /// Tokens the conversation may take before old turns are compacted
+    pub budget: ContextBudget,
+    /// Results of every executed script, in order
+    pub executed: Vec<Status>,
+    /// Script received from the model but not executed yet
+    pub pending: Option<String>,
+    /// Stored after each turn so the task can be resumed, None in dry-run mode
+    pub session: Option<Session>,
+    /// Live status line of a parallel run
+    pub board: Option<BoardEntry>,
+    /// Tokens of earlier runs of a resumed session
+    pub usage_before: Usage,
+    /// Markdown report of the last run
+    pub report_path: Option<PathBuf>,
 }
 
 impl Agent {
-    pub fn new(promt: Promt) -> Self {
-        fs::create_dir_all("log").ok();
+    pub fn new(promt: Promt, employee: &Employee, provider: Box<dyn ModelProvider>, options: ExecOptions) -> Self {
+        // The LogDir the prompt tells the agent about
+        let home = PathBuf::from(&employee.dir);
+        fs::create_dir_all(home.join("log")).ok();
 
         let now = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
-        let log_filename = format!("log/Agent_{}.log", now);
+        let log_filename = home.join("log").join(format!("Agent_{}.log", now));
 
         let system_message = promt.system.clone().unwrap_or_else(|| 
             "You are an autonomous coding agent. Respond only with DSL commands. Not md format.".to_string()
         );
 
-        let conversation_history = vec![
-            GptMessage {
-                role: "system".into(),
-                content: system_message,
-            },
-        ];
+        let conversation_history = vec![Message::new("system", system_message)];
 
         Self {
+            name: employee.name.clone(),
             promt,
+            home,
             current_script: 1,
             log_path: log_filename,
             conversation_history,
+            provider,
+            options,
+            checkpoints: None,
+            budget: ContextBudget::default(),
+            executed: Vec::new(),
+            pending: None,
+            session: None,
+            board: None,
+            usage_before: Usage::default(),
+            report_path: None,
         }
     }
 
-    pub fn run(&mut self, dir: &mut Dir) -> std::io::Result<()> {
-        self.log_event("🤖 Agent starting work...");
+    /// Agent which continues a stored session where it stopped
+    pub fn from_session(mut session: Session, employee: &Employee, provider: Box<dyn ModelProvider>, options: ExecOptions) -> Self {
+        let promt = Promt { message: Some(session.task.clone()), system: None, task: session.task.clone() };
+        let mut agent = Agent::new(promt, employee, provider, options);
+        agent.usage_before = session.usage;
+        agent.conversation_history = std::mem::take(&mut session.conversation_history);
+        agent.current_script = session.current_script;
+        agent.executed = std::mem::take(&mut session.executed);
+        agent.pending = session.pending.take();
+        agent.session = Some(session);
+        agent
+    }
 
-        let initial_prompt = self.promt.message.clone().unwrap_or_default();
-        self.conversation_history.push(GptMessage {
-            role: "user".into(),
-            content: initial_prompt,
+    pub fn run(&mut self, dir: &mut Dir) -> Result<(), OrchestraError> {
+        if self.conversation_history.len() > 1 {
+            self.log_event(&format!("⏯️ Agent resuming at script #{}...", self.current_script));
+        } else {
+            self.log_event("🤖 Agent starting work...");
+            let initial_prompt = self.promt.message.clone().unwrap_or_default();
+            self.conversation_history.push(Message::new("user", initial_prompt));
+        }
+
+        let result = self.work(dir);
+        let state = result.as_ref().map_or(SessionState::Failed, |state| *state);
+        self.save_session(state);
+        self.write_report(state);
+        self.show(match state {
+            SessionState::Finished => "finished",
+            SessionState::Aborted => "aborted",
+            _ => "failed",
         });
 
-        let mut script = match self.generate_script(None,None) {
-            Ok(s) => {
-                self.log_event(&format!("✅ Script #{} generated:\n{}", self.current_script, s));
-                s
-            }
-            Err(err) => {
-                self.log_event(&format!("❌ Failed to generate script: {}", err));
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, err.to_string()));
-            }
-        };
+        match state {
+            SessionState::Finished => self.log_event("🏁 Agent finished successfully."),
+            SessionState::Aborted => self.log_event("🛑 Agent stopped by operator."),
+            _ => self.log_event("💥 Agent stopped on an error."),
+        }
+        result.map(|_| ())
+    }
 
+    /// Ask for scripts and execute them until one ends without CALLBACK
+    fn work(&mut self, dir: &mut Dir) -> Result<SessionState, OrchestraError> {
         loop {
+            // A script received before the stop is executed without asking the model again
+            let script = match self.pending.clone() {
+                Some(script) => script,
+                None => {
+                    let (callback, feedback) = match self.executed.last() {
+                        None => (None, None),
+                        Some(status) => {
+                            if let Some(CMD::Aborted { command }) = status.running.last() {
+                                self.log_event(&format!("🛑 Aborted by operator at: {}", command));
+                                return Ok(SessionState::Aborted);
+                            }
+                            match self.next_request(&status.running) {
+                                Some((callback, feedback)) => (Some(callback), Some(feedback)),
+                                None => {
+                                    self.log_event("✅ No callback found, execution finished.");
+                                    return Ok(SessionState::Finished);
+                                }
+                            }
+                        }
+                    };
+
+                    let number = if callback.is_some() { self.current_script + 1 } else { self.current_script };
+                    if callback.is_some() {
+                        self.log_event(&format!("🔄 Requesting next script from the model (#{})...", number));
+                    }
+                    self.show(&format!("waiting for script #{}", number));
+                    let script = match self.generate_script(callback.as_deref(), feedback.as_deref()) {
+                        Ok(script) => script,
+                        Err(err) => {
+                            self.log_event(&format!("❌ Failed to generate script #{}: {}", number, err));
+                            return Err(OrchestraError::Provider(err));
+                        }
+                    };
+                    self.current_script = number;
+                    self.log_event(&format!("✅ Received script #{}:\n{}", self.current_script, script));
+
+                    self.pending = Some(script.clone());
+                    self.save_session(SessionState::Running);
+                    script
+                }
+            };
+
             self.log_event(&format!("📜 Executing script #{}", self.current_script));
+            self.show(&format!("running script #{}", self.current_script));
+            let results = parse_and_execute(dir, &script, &mut self.options)?;
+            self.checkpoint(&results.running);
+
+            self.pending = None;
+            self.executed.push(results);
+            self.save_session(SessionState::Running);
+        }
+    }
 
-            let results = parse_and_execute(dir, &script)?;
-            let mut callback_triggered = false;
-            let mut callback_msg = String::new();
+    /// Callback and execution feedback for the next request, None when the script has no CALLBACK
+    fn next_request(&self, commands: &[CMD]) -> Option<(String, String)> {
+        let mut callback = None;
+        for cmd in commands {
+            if let CMD::Callback(message) = cmd {
+                self.log_event(&format!("💬 Callback: {}", message));
+                callback = Some(message.clone());
+            }
+        }
 
-            let execution_feedback = self.collect_execution_feedback(&results.running);
+        // Failed commands go back to the model even without CALLBACK, so it can fix them
+        if callback.is_none() && commands.iter().any(CMD::is_failure) {
+            self.log_event("❌ Script had errors, asking the model to fix them");
+            callback = Some("Some commands failed, see the errors and fix them.".to_string());
+        }
 
-            for cmd in results.running {
-                if let CMD::Callback(message) = cmd {
-                    callback_triggered = true;
-                    callback_msg = message.clone();
-                    self.log_event(&format!("💬 Callback: {}", message));
-                }
+        callback.map(|callback| (callback, self.collect_execution_feedback(commands)))
+    }
+
+    fn show(&self, state: &str) {
+        if let Some(board) = &self.board {
+            board.set(state);
+        }
+    }
+
+    /// Store the progress, a session which cannot be written only costs the ability to resume
+    fn save_session(&mut self, state: SessionState) {
+        let usage = self.usage();
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        session.state = state;
+        session.current_script = self.current_script;
+        session.conversation_history = self.conversation_history.clone();
+        session.executed = self.executed.clone();
+        session.pending = self.pending.clone();
+        session.usage = usage;
+
+        if let Err(e) = session.save() {
+            self.log_event(&format!("⚠️ Session was not saved: {}", e));
+        }
+    }
+
+    /// Tokens of every model request of the task, earlier runs of a resumed session included
+    pub fn usage(&self) -> Usage {
+        self.usage_before + self.provider.usage()
+    }
+
+    /// Markdown and JSON report into the ReportDir the prompt tells the agent about
+    fn write_report(&mut self, state: SessionState) {
+        let mut report = Report::new(&self.name, &self.promt.task, state, &self.executed, self.usage());
+        report.session = self.session.as_ref().map(|s| s.id.clone());
+        report.dry_run = self.options.dry_run.is_some();
+
+        let id = report.session.clone().unwrap_or_else(|| {
+            let name = self.name.to_lowercase().replace(|c: char| !c.is_alphanumeric(), "-");
+            format!("{}-{}", name, Local::now().format("%Y%m%d-%H%M%S"))
+        });
+        match report.write(&self.home, &id) {
+            Ok(path) => {
+                self.log_event(&format!("📝 Report written to {}", path.display()));
+                self.report_path = Some(path);
             }
+            Err(e) => self.log_event(&format!("⚠️ Report was not written: {}", e)),
+        }
+    }
+
+    /// Changes collected in dry-run mode, None when the agent worked on disk
+    pub fn dry_run(&self) -> Option<&DryRun> {
+        self.options.dry_run.as_ref()
+    }
 
-            if callback_triggered {
-                self.current_script += 1;
-                self.log_event(&format!("🔄 Requesting next script from GPT (#{})...", self.current_script));
+    /// Commit the project after a script, the message is taken from its COMMENTS and CALLBACK
+    fn checkpoint(&mut self, commands: &[CMD]) {
+        let Some(checkpoints) = self.checkpoints.as_mut() else {
+            return;
+        };
 
-                match self.generate_script(Some(&callback_msg), Some(&execution_feedback)) {
-                    Ok(new_script) => {
-                        self.log_event(&format!("✅ Received script #{}:\n{}", self.current_script, new_script));
-                        script = new_script;
-                        continue;
-                    }
-                    Err(err) => {
-                        self.log_event(&format!("⚠️ GPT request failed: {}", err));
-                        break;
+        let mut message: Vec<String> = commands
+            .iter()
+            .filter_map(|cmd| match cmd {
+                CMD::Comments(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect();
+        if let Some(CMD::Callback(text)) = commands.iter().find(|cmd| matches!(cmd, CMD::Callback(_))) {
+            message.push(text.clone());
+        }
+        if message.is_empty() {
+            message.push(format!("Script #{}", self.current_script));
+        }
+
+        let branch = checkpoints.branch.clone();
+        match checkpoints.commit(&message.join("\n\n")) {
+            Ok(Some(commit)) => self.log_event(&format!("📌 Checkpoint {} on {}", &commit[..commit.len().min(10)], branch)),
+            Ok(None) => self.log_event("📌 Script changed nothing, no checkpoint"),
+            Err(err) => self.log_event(&format!("⚠️ Checkpoint failed: {}", err)),
+        }
+    }
+
+    /// Original state of every file the agent changed, empty when nothing was written
+    pub fn take_journal(&mut self) -> Snapshot {
+        std::mem::take(&mut self.options.journal)
+    }
+
+    /// What the agent did, for the manager who reviews it: file contents are left out, long output is cut
+    pub fn report(&self) -> String {
+        let mut report = Vec::new();
+        for (i, status) in self.executed.iter().enumerate() {
+            report.push(format!("Script #{}:", i + 1));
+            for cmd in &status.running {
+                let entry = self.collect_execution_feedback(std::slice::from_ref(cmd));
+                let lines: Vec<&str> = entry.lines().collect();
+                let keep = match cmd {
+                    CMD::OpenFile { .. } | CMD::OpenDir { .. } => lines[..1].to_vec(),
+                    _ if lines.len() > 12 => {
+                        let mut keep = lines[..2].to_vec();
+                        keep.push("…");
+                        keep.extend(&lines[lines.len() - 8..]);
+                        keep
                     }
-                }
-            } else {
-                self.log_event("✅ No callback found, execution finished.");
-                break;
+                    _ => lines,
+                };
+                report.extend(keep.iter().map(|l| format!("  {}", l)));
             }
         }
 
-        self.log_event("🏁 Agent finished successfully.");
-        Ok(())
+        if report.is_empty() {
+            "No scripts were executed.".to_string()
+        } else {
+            report.join("\n")
+        }
     }
 
     /// Собирает информацию из выполненных команд
@@ -158,6 +339,60 @@ impl Agent {
                 CMD::DeleteFile { path, line } => {
                     feedback.push(format!("🗑️ Deleted line {} from file: {}", line, path));
                 }
+                CMD::ReplaceLines { path, start, end, content } => {
+                    feedback.push(format!("🔁 Replaced lines {}-{} in file: {} with:\n{}", start, end, path, content));
+                }
+                CMD::DeleteLines { path, start, end } => {
+                    feedback.push(format!("🗑️ Deleted lines {}-{} from file: {}", start, end, path));
+                }
+                CMD::Patch { path, outcome } => match outcome {
+                    PatchOutcome::Applied { line } => {
+                        feedback.push(format!("🩹 Patched file: {} at line {}", path, line));
+                    }
+                    PatchOutcome::NotFound => {
+                        feedback.push(format!("❌ PATCH failed for {}: FIND text not found, open the file and copy the text exactly", path));
+                    }
+                    PatchOutcome::Ambiguous(n) => {
+                        feedback.push(format!("❌ PATCH failed for {}: ambiguous ({} matches), add surrounding lines to FIND", path, n));
+                    }
+                },
+                CMD::ApplyDiff { files } => {
+                    for report in files {
+                        let mut lines = vec![format!("🧩 Diff for file: {}", report.path)];
+                        if let Some(err) = &report.error {
+                            lines.push(format!("  ❌ file rejected: {}", err));
+                        }
+                        for hunk in &report.hunks {
+                            lines.push(match hunk {
+                                HunkResult::Applied { hunk, offset, fuzz } => {
+                                    format!("  ✅ hunk {} applied (offset {:+}, fuzz {})", hunk, offset, fuzz)
+                                }
+                                HunkResult::Rejected { hunk, reason } => {
+                                    format!("  ❌ hunk {} rejected: {}", hunk, reason)
+                                }
+                            });
+                        }
+                        feedback.push(lines.join("\n"));
+                    }
+                }
+                CMD::PathRejected { path, reason } => {
+                    feedback.push(format!("⛔ Path rejected: {} ({}), use paths inside the project", path, reason));
+                }
+                CMD::Skipped { command, reason } => {
+                    feedback.push(format!("⏭️ Not executed: {} ({})", command, reason));
+                }
+                CMD::Aborted { command } => {
+                    feedback.push(format!("🛑 Script aborted by operator at: {}", command));
+                }
+                CMD::Error { command, message } => {
+                    feedback.push(format!("❌ Command failed: {}\nError: {}", command, message));
+                }
+                CMD::RolledBack { paths } => {
+                    feedback.push(format!(
+                        "↩️ Script stopped after the error, changes to {} were rolled back. Send the whole fixed script again.",
+                        paths.join(", ")
+                    ));
+                }
                 CMD::Run { command, output, exit_code } => {
                     feedback.push(format!(
                         "🚀 Executed command: {}\n📤 Output (exit code {}):\n{}", 
@@ -167,8 +402,15 @@ impl Agent {
                 CMD::Callback(message) => {
                     feedback.push(format!("↩️ Callback: {}", message));
                 }
-                CMD::Unknown(text) => {
-                    feedback.push(format!("❓ Unknown command: {}", text));
+                CMD::ParseError { line, column, message } => {
+                    feedback.push(format!("❓ Parse error at line {}, column {}: {}", line, column, message));
+                }
+                CMD::Asked { to, question, answer } => {
+                    let question: String = question.lines().next().unwrap_or_default().chars().take(80).collect();
+                    feedback.push(format!("💡 Answer of {} to \"{}\":\n{}", to, question, answer));
+                }
+                CMD::Notified { to } => {
+                    feedback.push(format!("📨 Message left for {}, it reads it with its next request", to));
                 }
             }
         }
@@ -181,11 +423,6 @@ impl Agent {
     }
 
     fn generate_script(&mut self, callback: Option<&str>, execution_feedback: Option<&str>) -> Result<String, Box<dyn Error>> {
-        let api_key = std::env::var("OPENAI_API_KEY")
-            .expect("⚠️ OPENAI_API_KEY environment variable not set");
-
-        let client = Client::new();
-
         // Если есть callback, добавляем его в историю вместе с результатами выполнения
         if let Some(cb_msg) = callback {
             let feedback = execution_feedback.unwrap_or("No execution feedback available.");
@@ -197,40 +434,42 @@ impl Agent {
                 feedback, cb_msg
             );
             
-            self.conversation_history.push(GptMessage {
-                role: "user".into(),
-                content: user_message,
-            });
+            self.conversation_history.push(Message::new("user", user_message));
             
-            self.log_event(&format!("🧠 Sending GPT request (callback: {})", cb_msg));
+            self.log_event(&format!("🧠 Sending model request (callback: {})", cb_msg));
         } else {
-            self.log_event("🧠 Sending GPT request for first script...");
+            self.log_event("🧠 Sending model request for first script...");
         }
 
-        let request = GptRequest {
-            model: "gpt-4o-mini".to_string(),
-            messages: self.conversation_history.clone(),
-        };
-
-        let res = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .bearer_auth(api_key)
-            .json(&request)
-            .send()?;
+        // Messages of other agents go with the request
+        let notes = self.options.bus.as_mut().map(|bus| bus.inbox()).unwrap_or_default();
+        if !notes.is_empty()
+            && let Some(last) = self.conversation_history.last_mut()
+            && last.role == "user"
+        {
+            last.content.push_str(&format!("\n\n=== Messages from your team ===\n{}", notes.join("\n")));
+            self.log_event(&format!("📨 {} message(s) from the team", notes.len()));
+        }
 
-        if !res.status().is_success() {
-            return Err(format!("Bad response from GPT: {}", res.status()).into());
+        if let Some(c) = self.budget.compact(&mut self.conversation_history) {
+            self.log_event(&format!(
+                "🗜️ Context compacted: ~{} → ~{} tokens (limit {}), {} messages truncated, {} turns summarized",
+                c.before, c.after, self.budget.limit, c.truncated, c.summarized
+            ));
         }
 
-        let body: GptResponse = res.json()?;
-        let script = body.choices.first()
-            .map(|c| c.message.content.clone())
-            .unwrap_or_else(|| "COMMENTS \"No script generated\"".to_string());
+        let script = match self.provider.complete(&self.conversation_history) {
+            Ok(script) => script,
+            Err(err) => {
+                // Keep the history as it was, a resumed session sends the same request again
+                if callback.is_some() {
+                    self.conversation_history.pop();
+                }
+                return Err(err);
+            }
+        };
 
-        self.conversation_history.push(GptMessage {
-            role: "assistant".into(),
-            content: script.clone(),
-        });
+        self.conversation_history.push(Message::new("assistant", script.clone()));
 
         Ok(script)
     }
@@ -249,4 +488,89 @@ impl Agent {
         //dbg message for dev , 
         //dbg!(message);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coderun::policy::OnError;
+    use crate::config::LlmConfig;
+    use crate::provider::mock::MockProvider;
+
+    /// Run an agent on an empty temp project with the canned scripts, the root holds the project and the recorded requests
+    fn run_mock(test: &str, script: &str) -> (PathBuf, Agent) {
+        let root = std::env::temp_dir().join(format!("orchestra-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project")).unwrap();
+
+        let employee = Employee {
+            dir: root.join("alex").display().to_string(),
+            name: "Alex".to_string(),
+            task: "Python developer".to_string(),
+            run_mode: None,
+            on_error: None,
+            llm: LlmConfig::default(),
+        };
+        let provider = MockProvider::from_script(script, Some(root.join("requests.jsonl")));
+        let options = ExecOptions {
+            run_policy: Default::default(),
+            operator: None,
+            approval: false,
+            dry_run: None,
+            journal: Snapshot::default(),
+            on_error: OnError::Continue,
+            bus: None,
+        };
+        let promt = Promt { message: Some("Write calc.py".to_string()), system: None, task: "Write calc.py".to_string() };
+        let mut agent = Agent::new(promt, &employee, Box::new(provider), options);
+
+        let mut dir = Dir::read_from_path(root.join("project")).unwrap();
+        agent.run(&mut dir).unwrap();
+        (root, agent)
+    }
+
+    #[test]
+    fn mock_scripts_change_the_project() {
+        let (root, agent) = run_mock("e2e", include_str!("../tests/alex.dsl"));
+
+        let code = fs::read_to_string(root.join("project/calc.py")).unwrap();
+        assert_eq!(code.trim_end(), "import math\ndef area(r):\n    return math.pi * r * r");
+        assert_eq!(agent.executed.len(), 2);
+
+        // The second request carries the callback and the results of the first script
+        let requests = fs::read_to_string(root.join("requests.jsonl")).unwrap();
+        assert_eq!(requests.lines().count(), 2);
+        assert!(requests.lines().nth(1).unwrap().contains("calc.py created, switch it to math.pi"));
+        assert!(agent.report_path.as_ref().is_some_and(|p| p.exists()));
+
+        // The log keeps both scripts, what ran and the callback between them
+        let log = fs::read_to_string(&agent.log_path).unwrap();
+        assert!(log.contains("✅ Received script #1:\nCOMMENTS \"Add the area function\"\nCREATE FILE \"calc.py\""));
+        assert!(log.contains("💬 Callback: calc.py created, switch it to math.pi"));
+        assert!(log.contains("✅ Received script #2:\nPATCH FILE \"calc.py\" FIND \"3.14\" REPLACE \"math.pi\""));
+        assert!(log.contains("📜 Executing script #1") && log.contains("📜 Executing script #2"));
+        assert!(log.contains("🏁 Agent finished successfully."));
+        assert!(!log.contains("❌"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failures_go_back_without_callback() {
+        let failing = [
+            "PATCH FILE \"a.py\" FIND \"missing\" REPLACE \"x\"",
+            "OPEN FILE \"../outside.py\"",
+            "EDIT FILE \"a.py\" LINE",
+            "APPLY DIFF <<<EOF\n--- a/a.py\n+++ b/a.py\n@@ -1 +1 @@\n-missing\n+x\nEOF",
+        ];
+        for (i, command) in failing.iter().enumerate() {
+            let script = format!("CREATE FILE \"a.py\" WITH \"x = 1\"\n{}\n---8<---\nCOMMENTS \"fixed\"", command);
+            let (root, agent) = run_mock(&format!("failure-{}", i), &script);
+            assert_eq!(agent.executed.len(), 2, "{}", command);
+            let log = fs::read_to_string(&agent.log_path).unwrap();
+            assert!(log.contains("❌ Script had errors, asking the model to fix them"), "{}", command);
+            let requests = fs::read_to_string(root.join("requests.jsonl")).unwrap();
+            assert!(requests.lines().nth(1).unwrap().contains("Some commands failed"), "{}", command);
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// What every agent of a parallel run is doing, written by the agents and shown by the shell
+#[derive(Clone, Default)]
+pub struct StatusBoard {
+    states: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl StatusBoard {
+    /// Handle which updates the line of one agent
+    pub fn entry(&self, agent: &str) -> BoardEntry {
+        self.set(agent, "starting");
+        BoardEntry { board: self.clone(), agent: agent.to_string() }
+    }
+
+    fn set(&self, agent: &str, state: &str) {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        states.insert(agent.to_string(), state.to_string());
+    }
+
+    /// One line with every agent, e.g. `Alex: script #2 running | Emma: waiting for the model`
+    pub fn line(&self) -> String {
+        let states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        states
+            .iter()
+            .map(|(agent, state)| format!("{}: {}", agent, state))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+/// Line of one agent on the board
+#[derive(Clone)]
+pub struct BoardEntry {
+    board: StatusBoard,
+    agent: String,
+}
+
+impl BoardEntry {
+    pub fn set(&self, state: &str) {
+        self.board.set(&self.agent, state);
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::config::{Config, LlmConfig};
+use crate::context::ContextBudget;
+use crate::provider::{self, Message, ModelProvider};
+
+/// Messages between agents, used by the ASK and NOTIFY commands
+pub trait Bus: Send {
+    /// Answer of another agent to the question
+    fn ask(&mut self, to: &str, question: &str) -> Result<String, String>;
+    /// Leave a message for another agent, it reads it with its next request
+    fn notify(&mut self, to: &str, message: &str) -> Result<(), String>;
+    /// Messages left for this agent since the last call
+    fn inbox(&mut self) -> Vec<String>;
+}
+
+/// Employee or manager of the config who can be asked
+struct Member {
+    name: String,
+    role: String,
+    llm: LlmConfig,
+}
+
+/// Model of a member answering questions, the history keeps its earlier answers
+struct Responder {
+    provider: Box<dyn ModelProvider>,
+    history: Vec<Message>,
+    budget: ContextBudget,
+}
+
+#[derive(Default)]
+struct Inner {
+    inboxes: HashMap<String, Vec<String>>,
+    responders: HashMap<String, Arc<Mutex<Responder>>>,
+}
+
+/// In-process bus between every employee and manager of the config, shared by all agents of the shell
+#[derive(Clone, Default)]
+pub struct MessageBus {
+    members: Arc<Vec<Member>>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl MessageBus {
+    pub fn new(config: &Config) -> Self {
+        let employees = config.employee.iter().map(|e| Member {
+            name: e.name.clone(),
+            role: e.task.clone(),
+            llm: e.llm.or(&config.llm),
+        });
+        let managers = config.manager.iter().map(|m| Member {
+            name: m.name.clone(),
+            role: format!("{} manager of {}", m.level, m.team.join(", ")),
+            llm: m.llm.or(&config.llm),
+        });
+        MessageBus { members: Arc::new(employees.chain(managers).collect()), inner: Arc::default() }
+    }
+
+    /// Handle of one agent, its messages are sent in its name
+    pub fn mailbox(&self, agent: &str) -> Mailbox {
+        Mailbox { bus: self.clone(), agent: agent.to_string() }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn member(&self, name: &str) -> Result<&Member, String> {
+        self.members
+            .iter()
+            .find(|m| m.name == name)
+            .or_else(|| self.members.iter().find(|m| m.name.eq_ignore_ascii_case(name)))
+            .ok_or_else(|| {
+                let known: Vec<&str> = self.members.iter().map(|m| m.name.as_str()).collect();
+                format!("no agent \"{}\", known: {}", name, known.join(", "))
+            })
+    }
+
+    /// Responder of a member, its provider is built on the first question
+    fn responder(&self, member: &Member) -> Result<Arc<Mutex<Responder>>, String> {
+        let mut inner = self.lock();
+        if let Some(responder) = inner.responders.get(&member.name) {
+            return Ok(Arc::clone(responder));
+        }
+
+        let provider = provider::from_config(&member.llm).map_err(|e| e.to_string())?;
+        let system = format!(
+            "You are {0}, {1}, in a team of AI agents working on one project.\n\
+            Team members ask you questions. Answer briefly and concretely as {1}, in plain text, without DSL commands.\n\
+            You do not see the project files, only what the question contains. If you need code to answer, say which.",
+            member.name, member.role
+        );
+        let responder = Arc::new(Mutex::new(Responder {
+            provider,
+            history: vec![Message::new("system", system)],
+            budget: ContextBudget::from_config(&member.llm),
+        }));
+        inner.responders.insert(member.name.clone(), Arc::clone(&responder));
+        Ok(responder)
+    }
+}
+
+/// One agent's access to the bus
+pub struct Mailbox {
+    bus: MessageBus,
+    agent: String,
+}
+
+impl Bus for Mailbox {
+    fn ask(&mut self, to: &str, question: &str) -> Result<String, String> {
+        let member = self.bus.member(to)?;
+        if member.name == self.agent {
+            return Err("an agent cannot ASK itself".to_string());
+        }
+
+        // Questions to the same member wait for each other, its history stays in order
+        let responder = self.bus.responder(member)?;
+        let mut responder = responder.lock().unwrap_or_else(|e| e.into_inner());
+        let Responder { provider, history, budget } = &mut *responder;
+
+        history.push(Message::new("user", format!("=== Question from {} ===\n{}", self.agent, question)));
+        budget.compact(history);
+        match provider.complete(history) {
+            Ok(answer) => {
+                history.push(Message::new("assistant", answer.clone()));
+                Ok(answer)
+            }
+            Err(e) => {
+                history.pop();
+                Err(format!("{} could not answer: {}", member.name, e))
+            }
+        }
+    }
+
+    fn notify(&mut self, to: &str, message: &str) -> Result<(), String> {
+        let name = self.bus.member(to)?.name.clone();
+        if name == self.agent {
+            return Err("an agent cannot NOTIFY itself".to_string());
+        }
+        let note = format!("[{}] {}", self.agent, message);
+        self.bus.lock().inboxes.entry(name).or_default().push(note);
+        Ok(())
+    }
+
+    fn inbox(&mut self) -> Vec<String> {
+        self.bus.lock().inboxes.remove(&self.agent).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Alex and Lisa, Lisa answers with the mock responses and records what she is asked
+    fn bus(test: &str) -> (std::path::PathBuf, MessageBus) {
+        let root = std::env::temp_dir().join(format!("orchestra-bus-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("lisa.txt"), "Use UUIDs\n---8<---\nYes, v4\n").unwrap();
+        let config = format!(
+            "manager = []\n[project]\ndir = \"p\"\nignore_dir = []\nmax_size = 1024\n\
+            [[employee]]\ndir = \"a\"\nname = \"Alex\"\ntask = \"backend developer\"\n\
+            [[employee]]\ndir = \"l\"\nname = \"Lisa\"\ntask = \"database engineer\"\n\
+            provider = \"mock\"\nresponses = {:?}\nrecord = {:?}\n",
+            root.join("lisa.txt").display().to_string(),
+            root.join("lisa.jsonl").display().to_string(),
+        );
+        let config: Config = toml::from_str(&config).unwrap();
+        (root, MessageBus::new(&config))
+    }
+
+    #[test]
+    fn notes_wait_in_the_inbox_of_the_receiver() {
+        let (root, bus) = bus("notify");
+        let (mut alex, mut lisa) = (bus.mailbox("Alex"), bus.mailbox("Lisa"));
+
+        alex.notify("lisa", "users table has a new column").unwrap();
+        alex.notify("Lisa", "migration is in db/002.sql").unwrap();
+        assert!(alex.inbox().is_empty());
+        assert_eq!(lisa.inbox(), ["[Alex] users table has a new column", "[Alex] migration is in db/002.sql"]);
+        assert!(lisa.inbox().is_empty());
+
+        assert!(alex.notify("Alex", "hi").is_err());
+        assert_eq!(alex.notify("Bob", "hi").unwrap_err(), "no agent \"Bob\", known: Alex, Lisa");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn questions_go_to_the_model_of_the_member() {
+        let (root, bus) = bus("ask");
+        let mut alex = bus.mailbox("Alex");
+
+        assert_eq!(alex.ask("Lisa", "Which ids for users?").unwrap(), "Use UUIDs");
+        assert_eq!(alex.ask("Lisa", "Random ones?").unwrap(), "Yes, v4");
+        assert!(alex.ask("Alex", "Anyone?").is_err());
+
+        // The second question comes with the first one and its answer
+        let record = fs::read_to_string(root.join("lisa.jsonl")).unwrap();
+        let requests: Vec<&str> = record.lines().collect();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("Lisa, database engineer"), "{}", requests[0]);
+        for expected in ["=== Question from Alex ===\\nWhich ids for users?", "Use UUIDs", "Random ones?"] {
+            assert!(requests[1].contains(expected), "{:?} missing in {}", expected, requests[1]);
+        }
+
+        // Out of answers: the error goes back to the asking agent
+        assert!(alex.ask("Lisa", "And names?").unwrap_err().starts_with("Lisa could not answer"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,19 @@
+/// Answer of the operator to a previewed command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Review {
+    Approve,
+    Skip,
+    /// Run these DSL lines instead of the command
+    Edit(String),
+    /// Stop the whole script, the agent gets no more turns
+    Abort,
+}
+
+/// Human at the shell who can confirm actions of an agent, shared by the threads of a parallel run
+pub trait Operator: Send {
+    /// Ask whether a RUN command which the policy does not allow on its own may run
+    fn confirm_run(&mut self, command: &str, reason: &str) -> bool;
+
+    /// Show what a command is about to do (a diff for edits, the command line for RUN) and ask what to do with it
+    fn review(&mut self, action: &str, preview: &str) -> Review;
+}
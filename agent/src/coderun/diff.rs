@@ -0,0 +1,413 @@
+use serde::{Deserialize, Serialize};
+
+/// Max number of context lines which may be dropped from each end of a hunk when it does not match
+const MAX_FUZZ: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// 1-based line in the old file, taken from the `@@ -a,b +c,d @@` header
+    pub old_start: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+/// All hunks of one `--- a/x` / `+++ b/x` section, a `/dev/null` side is None
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FileDiff {
+    /// Path of the file in the project, the old one for deletions
+    pub fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+/// What happened with one hunk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum HunkResult {
+    /// Applied `offset` lines away from the header position, after dropping `fuzz` context lines per side
+    Applied { hunk: usize, offset: isize, fuzz: usize },
+    Rejected { hunk: usize, reason: String },
+}
+
+/// Per-file outcome of APPLY DIFF, `error` is set when the file could not be handled at all
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileReport {
+    pub path: String,
+    pub hunks: Vec<HunkResult>,
+    pub error: Option<String>,
+}
+
+fn header_path(raw: &str) -> Option<String> {
+    // Timestamps are separated by a tab: `--- a/x.py\t2024-01-01 ...`
+    let path = raw.split('\t').next().unwrap_or_default().trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(path.to_string())
+}
+
+/// `@@ -12,5 +12,7 @@ optional section` -> 12
+fn hunk_start(header: &str) -> Option<usize> {
+    let old = header.strip_prefix("@@")?.trim_start().strip_prefix('-')?;
+    let start = old.split([',', ' ']).next()?;
+    start.parse().ok()
+}
+
+/// `@@ -12,5 +12,7 @@` -> (5, 7), a missing count is 1
+fn hunk_counts(header: &str) -> Option<(usize, usize)> {
+    let mut ranges = header.strip_prefix("@@")?.split_whitespace();
+    let count = |range: Option<&str>, sign: char| -> Option<usize> {
+        let range = range?.strip_prefix(sign)?;
+        match range.split_once(',') {
+            Some((_, count)) => count.parse().ok(),
+            None => range.parse::<usize>().ok().map(|_| 1),
+        }
+    };
+    Some((count(ranges.next(), '-')?, count(ranges.next(), '+')?))
+}
+
+/// Parse a standard unified diff. Models often get the line counts in hunk headers wrong, so a hunk ends at
+/// the next `@@`, file header or end of input. The counts only decide that `--- x` / `+++ y` lines still
+/// inside them are a removed `-- x` and an added `++ y`, not the header of the next file.
+pub fn parse_unified_diff(text: &str) -> Result<Vec<FileDiff>, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut files: Vec<FileDiff> = Vec::new();
+    let mut i = 0;
+
+    let is_file_header = |i: usize| {
+        lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+    };
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if is_file_header(i) {
+            files.push(FileDiff {
+                old_path: header_path(&line[4..]),
+                new_path: header_path(&lines[i + 1][4..]),
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+
+        if line.starts_with("@@") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| format!("hunk at diff line {} has no --- / +++ file header", i + 1))?;
+            let old_start = hunk_start(line)
+                .ok_or_else(|| format!("malformed hunk header at diff line {}: {}", i + 1, line))?;
+
+            // Old and new lines the header still promises, unknown counts promise nothing
+            let (mut old_left, mut new_left) = hunk_counts(line).unwrap_or((0, 0));
+            let mut hunk = Hunk { old_start, lines: Vec::new() };
+            let mut pending_blank = 0;
+            i += 1;
+
+            while i < lines.len() && !lines[i].starts_with("@@") && (old_left + new_left > 0 || !is_file_header(i)) {
+                let body = lines[i];
+                let (old_line, new_line) = match body.chars().next() {
+                    Some('-') => (1, 0),
+                    Some('+') => (0, 1),
+                    Some('\\') => (0, 0),
+                    _ => (1, 1),
+                };
+                old_left = old_left.saturating_sub(old_line);
+                new_left = new_left.saturating_sub(new_line);
+
+                let parsed = match body.chars().next() {
+                    // A fully empty line is an empty context line with its leading space stripped,
+                    // unless it trails the hunk
+                    None => {
+                        pending_blank += 1;
+                        i += 1;
+                        continue;
+                    }
+                    Some(' ') => Some(HunkLine::Context(body[1..].to_string())),
+                    Some('-') => Some(HunkLine::Remove(body[1..].to_string())),
+                    Some('+') => Some(HunkLine::Add(body[1..].to_string())),
+                    // `\ No newline at end of file`
+                    Some('\\') => None,
+                    Some(_) => break,
+                };
+
+                if let Some(parsed) = parsed {
+                    for _ in 0..pending_blank {
+                        hunk.lines.push(HunkLine::Context(String::new()));
+                    }
+                    pending_blank = 0;
+                    hunk.lines.push(parsed);
+                }
+                i += 1;
+            }
+
+            file.hunks.push(hunk);
+            continue;
+        }
+
+        // `diff --git`, `index ...` and other noise between sections
+        i += 1;
+    }
+
+    if files.is_empty() {
+        return Err("no --- / +++ file header found".to_string());
+    }
+    if let Some(file) = files.iter().find(|f| f.hunks.is_empty()) {
+        return Err(format!("file {} has no hunks", file.path()));
+    }
+    if files.iter().any(|f| f.old_path.is_none() && f.new_path.is_none()) {
+        return Err("both sides of a file header are /dev/null".to_string());
+    }
+
+    Ok(files)
+}
+
+/// Old and new line sequences of a hunk after dropping `fuzz` context lines from each end
+fn sides(hunk: &Hunk, fuzz: usize) -> Option<(usize, Vec<&str>, Vec<&str>)> {
+    let lead = hunk.lines.iter().take_while(|l| matches!(l, HunkLine::Context(_))).count();
+    let trail = hunk.lines.iter().rev().take_while(|l| matches!(l, HunkLine::Context(_))).count();
+    if fuzz > 0 && (lead.min(trail) < fuzz || lead == hunk.lines.len()) {
+        return None;
+    }
+
+    let body = &hunk.lines[fuzz..hunk.lines.len() - fuzz];
+    let mut old = Vec::new();
+    let mut new = Vec::new();
+    for line in body {
+        match line {
+            HunkLine::Context(text) => {
+                old.push(text.as_str());
+                new.push(text.as_str());
+            }
+            HunkLine::Remove(text) => old.push(text.as_str()),
+            HunkLine::Add(text) => new.push(text.as_str()),
+        }
+    }
+    Some((fuzz, old, new))
+}
+
+/// Closest position at or after `min` where `needle` matches `lines`, searching outwards from `expected`
+fn find_near(lines: &[String], needle: &[&str], expected: usize, min: usize) -> Option<usize> {
+    if needle.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - needle.len();
+    let matches_at = |pos: usize| {
+        pos >= min && pos <= last && lines[pos..pos + needle.len()].iter().zip(needle).all(|(a, b)| a == b)
+    };
+
+    let expected = expected.min(last);
+    for distance in 0..=lines.len() {
+        if matches_at(expected + distance) {
+            return Some(expected + distance);
+        }
+        if distance <= expected && matches_at(expected - distance) {
+            return Some(expected - distance);
+        }
+    }
+    None
+}
+
+/// Apply hunks in order like `patch`: every hunk is searched near its header position, then with up to
+/// MAX_FUZZ context lines dropped. Rejected hunks are skipped, the others still apply.
+pub fn apply_hunks(original: &[String], hunks: &[Hunk]) -> (Vec<String>, Vec<HunkResult>) {
+    let mut lines = original.to_vec();
+    let mut results = Vec::new();
+    // Lines added minus lines removed by the hunks applied so far
+    let mut delta: isize = 0;
+    // Hunks may not overlap the region changed by the previous one
+    let mut min_pos = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let number = index + 1;
+        let header_pos = (hunk.old_start.max(1) as isize - 1 + delta).max(0) as usize;
+        let mut applied = false;
+
+        for fuzz in 0..=MAX_FUZZ {
+            let Some((dropped, old, new)) = sides(hunk, fuzz) else {
+                break;
+            };
+            let expected = header_pos + dropped;
+
+            let pos = if old.is_empty() {
+                Some(expected.clamp(min_pos, lines.len()))
+            } else {
+                find_near(&lines, &old, expected, min_pos)
+            };
+
+            if let Some(pos) = pos {
+                let offset = pos as isize - expected as isize;
+                lines.splice(pos..pos + old.len(), new.iter().map(|s| s.to_string()));
+                // Later hunks are most likely shifted by the same offset
+                delta += new.len() as isize - old.len() as isize + offset;
+                min_pos = pos + new.len();
+                results.push(HunkResult::Applied { hunk: number, offset, fuzz });
+                applied = true;
+                break;
+            }
+        }
+
+        if !applied {
+            results.push(HunkResult::Rejected {
+                hunk: number,
+                reason: format!("context near line {} not found", hunk.old_start),
+            });
+        }
+    }
+
+    (lines, results)
+}
+
+/// Above this many old*new lines the middle of a change is shown as plain remove/add instead of an LCS diff
+const MAX_LCS_CELLS: usize = 4_000_000;
+const CONTEXT: usize = 3;
+
+/// Render a unified diff between two versions of a file, used to preview changes to the operator
+pub fn render_diff(path: &str, old: &[String], new: &[String]) -> String {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+    if old_mid.is_empty() && new_mid.is_empty() {
+        return format!("--- a/{0}\n+++ b/{0}\n(no changes)", path);
+    }
+
+    // Edit script of the middle part: (' ' | '-' | '+', text)
+    let mut script: Vec<(char, &str)> = Vec::new();
+    if old_mid.len() * new_mid.len() > MAX_LCS_CELLS {
+        script.extend(old_mid.iter().map(|l| ('-', l.as_str())));
+        script.extend(new_mid.iter().map(|l| ('+', l.as_str())));
+    } else {
+        let (n, m) = (old_mid.len(), new_mid.len());
+        let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if old_mid[i] == new_mid[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                script.push((' ', old_mid[i].as_str()));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                script.push(('-', old_mid[i].as_str()));
+                i += 1;
+            } else {
+                script.push(('+', new_mid[j].as_str()));
+                j += 1;
+            }
+        }
+    }
+
+    let lead = prefix.min(CONTEXT);
+    let trail = suffix.min(CONTEXT);
+    let old_count = lead + script.iter().filter(|(c, _)| *c != '+').count() + trail;
+    let new_count = lead + script.iter().filter(|(c, _)| *c != '-').count() + trail;
+
+    // Like diff -u, an empty side starts at the line before the change
+    let start = |count: usize| if count == 0 { prefix - lead } else { prefix - lead + 1 };
+    let mut out = vec![
+        format!("--- a/{}", path),
+        format!("+++ b/{}", path),
+        format!("@@ -{},{} +{},{} @@", start(old_count), old_count, start(new_count), new_count),
+    ];
+    out.extend(old[prefix - lead..prefix].iter().map(|l| format!(" {}", l)));
+    out.extend(script.iter().map(|(c, l)| format!("{}{}", c, l)));
+    out.extend(old[old.len() - suffix..old.len() - suffix + trail].iter().map(|l| format!(" {}", l)));
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn header_like_lines_inside_a_counted_hunk_are_content() {
+        let diff = "--- a/notes.md\n+++ b/notes.md\n@@ -1,3 +1,3 @@\n title\n--- old rule\n+++ new rule\n end\n--- a/x.py\n+++ b/x.py\n@@ -1 +1 @@\n-a\n+b\n";
+        let files = parse_unified_diff(diff).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            files[0].hunks[0].lines,
+            vec![
+                HunkLine::Context("title".to_string()),
+                HunkLine::Remove("-- old rule".to_string()),
+                HunkLine::Add("++ new rule".to_string()),
+                HunkLine::Context("end".to_string()),
+            ]
+        );
+        assert_eq!(files[1].path(), "x.py");
+    }
+
+    #[test]
+    fn wrong_counts_do_not_swallow_the_next_file() {
+        // Counts too small: the hunk goes on until the next header
+        let diff = "--- a/a.py\n+++ b/a.py\n@@ -1,1 +1,1 @@\n x\n-y\n+z\n--- a/b.py\n+++ b/b.py\n@@ -1 +1 @@\n-a\n+b\n";
+        let files = parse_unified_diff(diff).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].hunks[0].lines.len(), 3);
+        assert_eq!(hunk_counts("@@ -3 +3,2 @@ fn main"), Some((1, 2)));
+    }
+
+    #[test]
+    fn hunk_is_found_away_from_its_header() {
+        let original = lines("a\nb\nc\nd\ne\nf");
+        let files = parse_unified_diff("--- a/x\n+++ b/x\n@@ -1,3 +1,3 @@\n c\n-d\n+D\n e\n").unwrap();
+        let (patched, results) = apply_hunks(&original, &files[0].hunks);
+        assert_eq!(patched, lines("a\nb\nc\nD\ne\nf"));
+        assert!(matches!(results[..], [HunkResult::Applied { hunk: 1, offset: 2, fuzz: 0 }]));
+    }
+
+    #[test]
+    fn stale_context_is_dropped_with_fuzz() {
+        let original = lines("a\nb\nc\nd\ne");
+        let files = parse_unified_diff("--- a/x\n+++ b/x\n@@ -2,3 +2,3 @@\n old\n-c\n+C\n stale\n").unwrap();
+        let (patched, results) = apply_hunks(&original, &files[0].hunks);
+        assert_eq!(patched, lines("a\nb\nC\nd\ne"));
+        assert!(matches!(results[..], [HunkResult::Applied { hunk: 1, offset: 0, fuzz: 1 }]));
+    }
+
+    #[test]
+    fn unmatched_hunk_is_rejected_and_the_rest_applies() {
+        let original = lines("a\nb\nc\nd\ne\nf\ng");
+        let diff = "--- a/x\n+++ b/x\n@@ -1,3 +1,3 @@\n q\n-r\n+R\n s\n@@ -5,3 +5,3 @@\n e\n-f\n+F\n g\n";
+        let files = parse_unified_diff(diff).unwrap();
+        let (patched, results) = apply_hunks(&original, &files[0].hunks);
+        assert_eq!(patched, lines("a\nb\nc\nd\ne\nF\ng"));
+        assert!(matches!(results[0], HunkResult::Rejected { hunk: 1, .. }));
+        assert!(matches!(results[1], HunkResult::Applied { hunk: 2, offset: 0, fuzz: 0 }));
+    }
+}
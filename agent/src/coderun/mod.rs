@@ -1 +1,6 @@
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod tokenizer;
+pub mod diff;
+pub mod policy;
+pub mod approval;
+pub mod workspace;
@@ -1,9 +1,18 @@
-use regex::Regex;
+use std::collections::VecDeque;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::process::Command as ProcessCommand;
 
-use crate::filesystem::{Dir, File};
+use serde::{Deserialize, Serialize};
+
+use crate::error::OrchestraError;
+use crate::filesystem::{Dir, LineEdit, PatchOutcome, Snapshot};
+use crate::bus::Bus;
+use super::approval::{Operator, Review};
+use super::policy::{OnError, RunDecision, RunPolicy};
+use super::diff::{apply_hunks, parse_unified_diff, render_diff, FileReport, HunkResult};
+use super::workspace::{DryRun, Workspace};
+use super::tokenizer::{tokenize, ParseError, Spanned, Token};
 
 #[derive(Debug, Clone)]
 pub enum CommandType {
@@ -13,9 +22,21 @@ pub enum CommandType {
     Edit,
     Delete,
     Insert,
+    Replace,
+    Patch,
+    ApplyDiff,
     Callback,
     Run,
-    Unknown,
+    /// Manager only: give a subtask to a team member
+    Assign,
+    /// Manager only: the reviewed work is done
+    Accept,
+    /// Manager only: send the reviewed work back with feedback
+    Revise,
+    /// Question to another agent, the answer is part of the results
+    Ask,
+    /// Message for another agent, no answer
+    Notify,
 }
 
 #[derive(Debug, Clone)]
@@ -31,12 +52,22 @@ pub struct Command {
     pub create_type: Option<CreateType>,
     pub file: Option<String>,
     pub line: Option<usize>,
+    /// Last line of a LINES a-b range
+    pub end_line: Option<usize>,
+    /// Text to search for, PATCH only
+    pub find: Option<String>,
     pub content: Option<String>,
+    /// Content as file lines, an empty block has none while an empty string is one empty line
+    pub lines: Option<Vec<String>>,
+    /// Team member of ASSIGN, agent of ASK and NOTIFY
+    pub target: Option<String>,
+    #[allow(dead_code)]
     pub subcommands: Vec<Command>,
 }
 
 /// List of executed commands 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum CMD {
     Comments(String),
     CreateDir(String),
@@ -46,13 +77,62 @@ pub enum CMD {
     EditFile { path: String, line: usize, content: String },
     InsertFile { path: String, line: usize, content: String },
     DeleteFile { path: String, line: usize },
+    ReplaceLines { path: String, start: usize, end: usize, content: String },
+    DeleteLines { path: String, start: usize, end: usize },
+    Patch { path: String, outcome: PatchOutcome },
+    ApplyDiff { files: Vec<FileReport> },
+    PathRejected { path: String, reason: String },
     Callback(String),
     Run { command: String, output: String, exit_code: i32 },
-    Unknown(String),
+    ParseError { line: usize, column: usize, message: String },
+    /// Operator skipped the command or replaced it with own commands
+    Skipped { command: String, reason: String },
+    /// Operator stopped the script at this command
+    Aborted { command: String },
+    /// Command failed, e.g. OPEN FILE on a missing path
+    Error { command: String, message: String },
+    /// Changes of the stopped script were undone
+    RolledBack { paths: Vec<String> },
+    /// Answer of another agent to ASK
+    Asked { to: String, question: String, answer: String },
+    /// NOTIFY left a message in the inbox of another agent
+    Notified { to: String },
+}
+
+impl CMD {
+    /// Command which did not do what the model asked, the model has to hear about it
+    pub fn is_failure(&self) -> bool {
+        match self {
+            CMD::Error { .. } | CMD::ParseError { .. } | CMD::PathRejected { .. } => true,
+            CMD::Patch { outcome, .. } => !matches!(outcome, PatchOutcome::Applied { .. }),
+            CMD::ApplyDiff { files } => files
+                .iter()
+                .any(|f| f.error.is_some() || f.hunks.iter().any(|h| matches!(h, HunkResult::Rejected { .. }))),
+            _ => false,
+        }
+    }
+}
+
+/// Per-agent settings the executor needs while running scripts
+#[derive(Default)]
+pub struct ExecOptions {
+    pub run_policy: RunPolicy,
+    /// Human who answers questions, None means every question is answered with no
+    pub operator: Option<Box<dyn Operator>>,
+    /// Stop before every change and RUN and let the operator approve, skip, edit or abort it
+    pub approval: bool,
+    /// Simulate scripts in memory: the project on disk is not changed and RUN is not executed
+    pub dry_run: Option<DryRun>,
+    /// Original state of every file changed by the successful scripts, lets the operator undo the task
+    pub journal: Snapshot,
+    /// What happens with the rest of a script after a command fails
+    pub on_error: OnError,
+    /// Other agents for ASK and NOTIFY, None means the agent works alone
+    pub bus: Option<Box<dyn Bus>>,
 }
 
 /// Status which history 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Status {
     pub running: Vec<CMD>,
 }
@@ -67,146 +147,375 @@ impl Status {
     }
 }
 
-/// Parse one line DLS
-pub fn parse_command(line: &str) -> Command {
-    let trimmed = line.trim();
-
-    let re_comments = Regex::new(r#"^COMMENTS\s+"(.*)""#).unwrap();
-    if let Some(caps) = re_comments.captures(trimmed) {
-        return Command {
-            command_type: CommandType::Comments,
-            body: caps[1].to_string(),
+impl Command {
+    fn new(command_type: CommandType, body: String) -> Self {
+        Command {
+            command_type,
+            body,
             create_type: None,
             file: None,
             line: None,
+            end_line: None,
+            find: None,
             content: None,
+            lines: None,
+            target: None,
             subcommands: vec![],
-        };
+        }
     }
 
-    let re_create = Regex::new(r#"^CREATE\s+(DIR|FILE)\s+"(.*)""#).unwrap();
-    if let Some(caps) = re_create.captures(trimmed) {
-        let create_type = match &caps[1] {
-            "DIR" => CreateType::Dir,
-            "FILE" => CreateType::File,
-            _ => unreachable!(),
-        };
-        return Command {
-            command_type: CommandType::Create,
-            body: trimmed.to_string(),
-            create_type: Some(create_type),
-            file: Some(caps[2].to_string()),
-            line: None,
-            content: None,
-            subcommands: vec![],
-        };
+    /// Content of EDIT, INSERT, REPLACE and CREATE, kept as text for the reports too
+    fn set_lines(&mut self, lines: Vec<String>) {
+        self.content = Some(lines.join("\n"));
+        self.lines = Some(lines);
     }
+}
 
-    let re_open = Regex::new(r#"^OPEN\s+(DIR|FILE)\s+"(.*)""#).unwrap();
-    if let Some(caps) = re_open.captures(trimmed) {
-        let create_type = match &caps[1] {
-            "DIR" => CreateType::Dir,
-            "FILE" => CreateType::File,
-            _ => unreachable!(),
-        };
-        return Command {
-            command_type: CommandType::Open,
-            body: trimmed.to_string(),
-            create_type: Some(create_type),
-            file: Some(caps[2].to_string()),
-            line: None,
-            content: None,
-            subcommands: vec![],
-        };
+/// Walks over the tokens of one command and reports what was expected where
+struct Cursor<'a> {
+    tokens: &'a [Spanned],
+    pos: usize,
+    line: usize,
+    end_column: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(tokens: &'a [Spanned], line: usize, end_column: usize) -> Self {
+        Cursor { tokens, pos: 0, line, end_column }
     }
 
-    let re_insert = Regex::new(r#"^INSERT\s+FILE\s+"(.*)"\s+LINE\s+(\d+)\s+INSERT\s+"(.*)""#).unwrap();
-    if let Some(caps) = re_insert.captures(trimmed) {
-        return Command {
-            command_type: CommandType::Insert,
-            body: trimmed.to_string(),
-            create_type: Some(CreateType::File),
-            file: Some(caps[1].to_string()),
-            line: Some(caps[2].parse().unwrap_or(0)),
-            content: Some(caps[3].to_string()),
-            subcommands: vec![],
-        };
+    fn error(&self, message: String) -> ParseError {
+        let column = self.tokens.get(self.pos).map_or(self.end_column, |t| t.column);
+        ParseError { line: self.line, column, message }
     }
 
-    let re_delete = Regex::new(r#"^DELETE\s+FILE\s+"(.*)"\s+LINE\s+(\d+)"#).unwrap();
-    if let Some(caps) = re_delete.captures(trimmed) {
-        return Command {
-            command_type: CommandType::Delete,
-            body: trimmed.to_string(),
-            create_type: Some(CreateType::File),
-            file: Some(caps[1].to_string()),
-            line: Some(caps[2].parse().unwrap_or(0)),
-            content: None,
-            subcommands: vec![],
-        };
+    fn peek_word(&self) -> Option<&str> {
+        match self.tokens.get(self.pos).map(|t| &t.token) {
+            Some(Token::Word(word)) => Some(word.as_str()),
+            _ => None,
+        }
     }
 
-    let re_edit = Regex::new(r#"^EDIT\s+(FILE|DIR)\s+"(.*)"\s+LINE\s+(\d+)\s+PUT\s+"(.*)""#).unwrap();
-    if let Some(caps) = re_edit.captures(trimmed) {
-        let create_type = match &caps[1] {
-            "DIR" => CreateType::Dir,
-            "FILE" => CreateType::File,
-            _ => unreachable!(),
-        };
-        return Command {
-            command_type: CommandType::Edit,
-            body: trimmed.to_string(),
-            create_type: Some(create_type),
-            file: Some(caps[2].to_string()),
-            line: Some(caps[3].parse().unwrap_or(0)),
-            content: Some(caps[4].to_string()),
-            subcommands: vec![],
-        };
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_word() == Some(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
     }
 
-    let re_run = Regex::new(r#"^RUN\s+"(.*)""#).unwrap();
-    if let Some(caps) = re_run.captures(trimmed) {
-        return Command {
-            command_type: CommandType::Run,
-            body: caps[1].to_string(),
-            create_type: None,
-            file: None,
-            line: None,
-            content: None,
-            subcommands: vec![],
-        };
+    fn keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}", keyword)))
+        }
     }
 
-    let re_callback = Regex::new(r#"^CALLBACK\s+"(.*)""#).unwrap();
-    if let Some(caps) = re_callback.captures(trimmed) {
-        return Command {
-            command_type: CommandType::Callback,
-            body: caps[1].to_string(),
-            create_type: None,
-            file: None,
-            line: None,
-            content: None,
-            subcommands: vec![],
-        };
+    fn one_of(&mut self, keywords: &[&str]) -> Result<String, ParseError> {
+        match self.peek_word() {
+            Some(word) if keywords.contains(&word) => {
+                let word = word.to_string();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.error(format!("expected {}", keywords.join(" or ")))),
+        }
+    }
+
+    /// Quoted string only, used for paths
+    fn string(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.tokens.get(self.pos).map(|t| &t.token) {
+            Some(Token::Str(value)) => {
+                let value = value.clone();
+                self.pos += 1;
+                Ok(value)
+            }
+            _ => Err(self.error(format!("expected quoted {}", what))),
+        }
+    }
+
+    /// Quoted string or <<<TAG block
+    fn text(&mut self, what: &str) -> Result<String, ParseError> {
+        Ok(self.lines(what)?.join("\n"))
+    }
+
+    /// Quoted string or <<<TAG block as file lines, `\n` in a string starts a new line
+    fn lines(&mut self, what: &str) -> Result<Vec<String>, ParseError> {
+        match self.tokens.get(self.pos).map(|t| &t.token) {
+            Some(Token::Str(value)) => {
+                let lines = value.split('\n').map(str::to_string).collect();
+                self.pos += 1;
+                Ok(lines)
+            }
+            Some(Token::Block { body: Some(body), .. }) => {
+                let body = body.clone();
+                self.pos += 1;
+                Ok(body)
+            }
+            Some(Token::Block { tag, body: None }) => {
+                Err(self.error(format!("block <<<{} has no body", tag)))
+            }
+            _ => Err(self.error(format!("expected quoted {} or <<<TAG block", what))),
+        }
+    }
+
+    fn number(&mut self, what: &str) -> Result<usize, ParseError> {
+        match self.peek_word().map(|w| w.parse::<usize>()) {
+            Some(Ok(value)) => {
+                self.pos += 1;
+                Ok(value)
+            }
+            _ => Err(self.error(format!("expected {} number", what))),
+        }
     }
 
-    Command {
-        command_type: CommandType::Unknown,
-        body: trimmed.to_string(),
-        create_type: None,
-        file: None,
-        line: None,
-        content: None,
-        subcommands: vec![],
+    /// Line range `a-b`, a single `a` means one line
+    fn range(&mut self) -> Result<(usize, usize), ParseError> {
+        let parsed = self.peek_word().and_then(|word| {
+            let (start, end) = word.split_once('-').unwrap_or((word, word));
+            Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+        });
+
+        match parsed {
+            Some((start, end)) if start >= 1 && start <= end => {
+                self.pos += 1;
+                Ok((start, end))
+            }
+            _ => Err(self.error("expected line range like 3-7".to_string())),
+        }
     }
+
+    fn finish(&self) -> Result<(), ParseError> {
+        if self.pos < self.tokens.len() {
+            Err(self.error("unexpected trailing input".to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn create_type(word: &str) -> CreateType {
+    if word == "DIR" { CreateType::Dir } else { CreateType::File }
 }
 
-/// Parser for the entire script, goes through each line of code
-pub fn parse_script(script: &str) -> Vec<Command> {
-    script
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(parse_command)
+/// Build a command from the tokens of one line, blocks must already carry their body
+fn parse_tokens(tokens: &[Spanned], line: usize, raw: &str) -> Result<Command, ParseError> {
+    let mut cur = Cursor::new(tokens, line, raw.chars().count() + 1);
+    let body = raw.trim().to_string();
+
+    let keyword = match cur.peek_word() {
+        Some(word) => word.to_string(),
+        None => return Err(cur.error("expected command".to_string())),
+    };
+    cur.pos += 1;
+
+    let command = match keyword.as_str() {
+        "COMMENTS" => Command::new(CommandType::Comments, cur.text("comment")?),
+        "CALLBACK" => Command::new(CommandType::Callback, cur.text("message")?),
+        "RUN" => Command::new(CommandType::Run, cur.string("command")?),
+        "CREATE" => {
+            let kind = cur.one_of(&["DIR", "FILE"])?;
+            let mut cmd = Command::new(CommandType::Create, body);
+            cmd.file = Some(cur.string("path")?);
+            if kind == "FILE" && cur.eat_keyword("WITH") {
+                cmd.set_lines(cur.lines("content")?);
+            }
+            cmd.create_type = Some(create_type(&kind));
+            cmd
+        }
+        "OPEN" => {
+            let kind = cur.one_of(&["DIR", "FILE"])?;
+            let mut cmd = Command::new(CommandType::Open, body);
+            cmd.create_type = Some(create_type(&kind));
+            cmd.file = Some(cur.string("path")?);
+            cmd
+        }
+        "EDIT" => {
+            let kind = cur.one_of(&["DIR", "FILE"])?;
+            let mut cmd = Command::new(CommandType::Edit, body);
+            cmd.create_type = Some(create_type(&kind));
+            cmd.file = Some(cur.string("path")?);
+            cur.keyword("LINE")?;
+            cmd.line = Some(cur.number("line")?);
+            cur.keyword("PUT")?;
+            cmd.set_lines(cur.lines("content")?);
+            cmd
+        }
+        "INSERT" => {
+            cur.keyword("FILE")?;
+            let mut cmd = Command::new(CommandType::Insert, body);
+            cmd.create_type = Some(CreateType::File);
+            cmd.file = Some(cur.string("path")?);
+            cur.keyword("LINE")?;
+            cmd.line = Some(cur.number("line")?);
+            cur.eat_keyword("INSERT");
+            cmd.set_lines(cur.lines("content")?);
+            cmd
+        }
+        "DELETE" => {
+            cur.keyword("FILE")?;
+            let mut cmd = Command::new(CommandType::Delete, body);
+            cmd.create_type = Some(CreateType::File);
+            cmd.file = Some(cur.string("path")?);
+            if cur.eat_keyword("LINES") {
+                let (start, end) = cur.range()?;
+                cmd.line = Some(start);
+                cmd.end_line = Some(end);
+            } else {
+                cur.keyword("LINE")?;
+                cmd.line = Some(cur.number("line")?);
+            }
+            cmd
+        }
+        "REPLACE" => {
+            cur.keyword("FILE")?;
+            let mut cmd = Command::new(CommandType::Replace, body);
+            cmd.create_type = Some(CreateType::File);
+            cmd.file = Some(cur.string("path")?);
+            cur.keyword("LINES")?;
+            let (start, end) = cur.range()?;
+            cmd.line = Some(start);
+            cmd.end_line = Some(end);
+            cur.keyword("WITH")?;
+            cmd.set_lines(cur.lines("content")?);
+            cmd
+        }
+        "PATCH" => {
+            cur.keyword("FILE")?;
+            let mut cmd = Command::new(CommandType::Patch, body);
+            cmd.create_type = Some(CreateType::File);
+            cmd.file = Some(cur.string("path")?);
+            cur.keyword("FIND")?;
+            let find = cur.text("search text")?;
+            if find.is_empty() {
+                cur.pos -= 1;
+                return Err(cur.error("FIND text must not be empty".to_string()));
+            }
+            cmd.find = Some(find);
+            cur.keyword("REPLACE")?;
+            cmd.content = Some(cur.text("replacement")?);
+            cmd
+        }
+        "APPLY" => {
+            cur.keyword("DIFF")?;
+            let diff = cur.text("diff")?;
+            // Validate now so a malformed diff is reported with the script line
+            if let Err(message) = parse_unified_diff(&diff) {
+                cur.pos -= 1;
+                return Err(cur.error(format!("invalid diff: {}", message)));
+            }
+            let mut cmd = Command::new(CommandType::ApplyDiff, body);
+            cmd.content = Some(diff);
+            cmd
+        }
+        "ASSIGN" => {
+            let mut cmd = Command::new(CommandType::Assign, body);
+            cmd.target = Some(cur.string("team member")?);
+            cmd.content = Some(cur.text("subtask")?);
+            cmd
+        }
+        "ACCEPT" => {
+            let note = if cur.finish().is_ok() { String::new() } else { cur.text("note")? };
+            Command::new(CommandType::Accept, note)
+        }
+        "REVISE" => Command::new(CommandType::Revise, cur.text("feedback")?),
+        "ASK" | "NOTIFY" => {
+            let kind = if keyword == "ASK" { CommandType::Ask } else { CommandType::Notify };
+            let mut cmd = Command::new(kind, body);
+            cmd.target = Some(cur.string("agent")?);
+            cmd.content = Some(cur.text(if keyword == "ASK" { "question" } else { "message" })?);
+            cmd
+        }
+        other => {
+            cur.pos -= 1;
+            return Err(cur.error(format!("unknown command `{}`", other)));
+        }
+    };
+
+    cur.finish()?;
+    Ok(command)
+}
+
+/// Parse one line DLS
+#[allow(dead_code)]
+pub fn parse_command(line: &str) -> Result<Command, ParseError> {
+    let tokens = tokenize(line, 1)?;
+    parse_tokens(&tokens, 1, line)
+}
+
+/// Parser for the entire script, goes through each line of code.
+/// A `<<<TAG` marker opens a block, every next line up to a line with only `TAG` becomes its content;
+/// several markers on one line take their blocks in order.
+/// Malformed lines are returned as errors so the rest of the script still runs.
+pub fn parse_script(script: &str) -> Vec<Result<Command, ParseError>> {
+    let mut commands = Vec::new();
+    let mut lines = script.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index + 1;
+
+        let mut tokens = match tokenize(line, line_number) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                commands.push(Err(err));
+                // The body of a block on a broken line is content, it must not run as commands
+                for tag in block_tags(line) {
+                    for (_, next) in lines.by_ref() {
+                        if next.trim() == tag {
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+        };
+
+        let mut unclosed = None;
+        for spanned in tokens.iter_mut() {
+            if let Token::Block { tag, body } = &mut spanned.token {
+                let mut block = Vec::new();
+                let mut closed = false;
+                for (_, next) in lines.by_ref() {
+                    if next.trim() == tag.as_str() {
+                        closed = true;
+                        break;
+                    }
+                    block.push(next);
+                }
+                if !closed {
+                    unclosed = Some(ParseError {
+                        line: line_number,
+                        column: spanned.column,
+                        message: format!("block <<<{} is never closed", tag),
+                    });
+                    break;
+                }
+                *body = Some(block.into_iter().map(str::to_string).collect());
+            }
+        }
+
+        match unclosed {
+            Some(err) => commands.push(Err(err)),
+            None => commands.push(parse_tokens(&tokens, line_number, line)),
+        }
+    }
+
+    commands
+}
+
+/// Tags of the `<<<TAG` markers in a raw line, used when the line itself does not tokenize
+fn block_tags(line: &str) -> Vec<String> {
+    line.split("<<<")
+        .skip(1)
+        .filter_map(|rest| {
+            let tag: String = rest.trim_start().chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+            (!tag.is_empty()).then_some(tag)
+        })
         .collect()
 }
 
@@ -267,191 +576,662 @@ fn execute_shell_command(command: &str, working_dir: &Path) -> io::Result<(Strin
             ))
         }
         Err(RecvTimeoutError::Disconnected) => {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Command execution thread disconnected"
-            ))
+            Err(io::Error::other("Command execution thread disconnected"))
         }
     }
 }
 
-/// Run the cmd, waiting for Calback 
-pub fn parse_and_execute(dir: &mut Dir, script: &str) -> io::Result<Status> {
-    let commands = parse_script(script);
-    let mut status = Status::new();
+/// Apply every file section of a unified diff to the project, a failing file or hunk does not stop the others
+fn apply_diff(ws: &mut Workspace, diff: &str) -> io::Result<Vec<FileReport>> {
+    let files = parse_unified_diff(diff).map_err(io::Error::other)?;
+    let mut reports = Vec::new();
 
-    for cmd in commands {
-        match cmd.command_type {
-            CommandType::Comments => {
-                println!("💬 {}", cmd.body);
-                status.add(CMD::Comments(cmd.body.clone()));
+    for file_diff in &files {
+        let path = match ws.dir.resolve(file_diff.path()) {
+            Ok(relative) => relative.to_string_lossy().to_string(),
+            Err(rejected) => {
+                reports.push(FileReport {
+                    path: file_diff.path().to_string(),
+                    hunks: Vec::new(),
+                    error: Some(rejected.reason),
+                });
+                continue;
             }
+        };
+        let mut report = FileReport { path: path.clone(), hunks: Vec::new(), error: None };
 
-            CommandType::Create => match cmd.create_type {
-                Some(CreateType::Dir) => {
-                    //println!("📁 Creating dir: {:?}", cmd.file);
-                    if let Some(name) = cmd.file.clone() {
-                        dir.create_dir(&name)?;
-                        status.add(CMD::CreateDir(name));
+        match (&file_diff.old_path, &file_diff.new_path) {
+            // New file: every hunk is applied on an empty file
+            (None, Some(_)) => {
+                if ws.exists(&path) {
+                    report.error = Some("file already exists".to_string());
+                } else {
+                    let (lines, results) = apply_hunks(&[], &file_diff.hunks);
+                    ws.create_file(&path, &lines)?;
+                    report.hunks = results;
+                }
+            }
+            // Deleted file: removed only when its hunks remove every line
+            (Some(_), None) => match ws.read(&path) {
+                Ok(current) => {
+                    let (lines, results) = apply_hunks(&current, &file_diff.hunks);
+                    let clean = results.iter().all(|h| matches!(h, HunkResult::Applied { .. }));
+                    if clean && lines.is_empty() {
+                        ws.remove_file(&path)?;
+                    } else {
+                        report.error = Some("file content differs from the diff, not deleted".to_string());
                     }
+                    report.hunks = results;
                 }
-                Some(CreateType::File) => {
-                    //println!("📄 Creating file: {:?}", cmd.file);
-                    if let Some(name) = cmd.file.clone() {
-                        dir.create_file(&name, None)?;
-                        status.add(CMD::CreateFile(name));
+                Err(e) => report.error = Some(e.to_string()),
+            },
+            _ => match ws.read(&path) {
+                Ok(current) => {
+                    let (lines, results) = apply_hunks(&current, &file_diff.hunks);
+                    if results.iter().any(|h| matches!(h, HunkResult::Applied { .. })) {
+                        ws.edit(&path, &LineEdit::Set(lines))?;
                     }
+                    report.hunks = results;
                 }
-                None => eprintln!("⚠️ CREATE missing type"),
+                Err(e) => report.error = Some(e.to_string()),
             },
+        }
+
+        reports.push(report);
+    }
 
-            CommandType::Open => match cmd.create_type {
-                Some(CreateType::Dir) => {
-                    //println!("📂 Opening dir: {:?}", cmd.file);
-                    if let Some(path) = cmd.file.clone() {
-                        let full_path = if Path::new(&path).is_absolute() {
-                            PathBuf::from(&path)
-                        } else {
-                            dir.path.join(&path)
-                        };
-                        
-                        let opened = Dir::read_from_path(&full_path)?;
-                        let content = opened.pretty_print();
-                        println!("{}", content);
-                        status.add(CMD::OpenDir { 
-                            path, 
-                            content 
-                        });
+    Ok(reports)
+}
+
+/// Content change of an EDIT/INSERT/DELETE/REPLACE/PATCH command
+fn line_edit(cmd: &Command) -> Option<LineEdit> {
+    let lines = || cmd.lines.clone();
+    match cmd.command_type {
+        CommandType::Edit => Some(LineEdit::Edit { line: cmd.line?, lines: lines()? }),
+        CommandType::Insert => Some(LineEdit::Insert { line: cmd.line?, lines: lines()? }),
+        CommandType::Replace => Some(LineEdit::Replace { start: cmd.line?, end: cmd.end_line?, lines: lines()? }),
+        CommandType::Delete => {
+            let start = cmd.line?;
+            Some(LineEdit::Delete { start, end: cmd.end_line.unwrap_or(start) })
+        }
+        CommandType::Patch => Some(LineEdit::Patch { find: cmd.find.clone()?, replace: cmd.content.clone()? }),
+        _ => None,
+    }
+}
+
+/// What a command is about to do, None for commands which change nothing
+fn preview(ws: &Workspace, cmd: &Command, decision: &RunDecision) -> Option<String> {
+    let path = cmd.file.clone().unwrap_or_default();
+    match cmd.command_type {
+        CommandType::Comments | CommandType::Open | CommandType::Callback => None,
+        CommandType::Assign | CommandType::Accept | CommandType::Revise => None,
+        CommandType::Ask | CommandType::Notify => None,
+        CommandType::Run => match decision {
+            RunDecision::Ask(reason) => Some(format!("$ {}\n({})", cmd.body, reason)),
+            _ => Some(format!("$ {}", cmd.body)),
+        },
+        CommandType::ApplyDiff => cmd.content.clone(),
+        CommandType::Create => match cmd.create_type {
+            Some(CreateType::Dir) => Some(format!("new directory {}", path)),
+            _ => {
+                let lines = cmd.lines.as_deref().unwrap_or_default();
+                Some(render_diff(&path, &[], lines))
+            }
+        },
+        _ => {
+            let edit = line_edit(cmd)?;
+            Some(match ws.simulate(&path, &edit) {
+                Ok((old, new, PatchOutcome::Applied { .. })) => render_diff(&path, &old, &new),
+                Ok((_, _, PatchOutcome::NotFound)) => format!("{}: PATCH text not found, nothing will change", path),
+                Ok((_, _, PatchOutcome::Ambiguous(n))) => format!("{}: PATCH text found {} times, nothing will change", path, n),
+                Err(e) => format!("{}: cannot preview, the command will fail: {}", path, e),
+            })
+        }
+    }
+}
+
+/// Run the cmd, waiting for Calback.
+/// Failed commands are recorded in the status. With on_error = stop, the default, the first one ends the script and every file it touched
+/// is restored; with continue the script runs to its end and keeps its changes. The only error is a rollback which could not be written.
+pub fn parse_and_execute(dir: &mut Dir, script: &str, options: &mut ExecOptions) -> Result<Status, OrchestraError> {
+    let mut snapshot = Snapshot::default();
+
+    let (mut status, stopped) = execute_script(dir, script, options, &mut snapshot);
+    // on_error = stop: the failed script is undone, the model gets the error and the rollback
+    if stopped {
+        if !snapshot.is_empty() {
+            let paths = snapshot.paths();
+            eprintln!("↩️ Script stopped, rolling back: {}", paths.join(", "));
+            snapshot.restore(dir)?;
+            status.add(CMD::RolledBack { paths });
+        }
+    } else {
+        options.journal.merge(snapshot);
+    }
+    Ok(status)
+}
+
+/// Status and whether the script was stopped by a failed command
+fn execute_script(
+    dir: &mut Dir,
+    script: &str,
+    options: &mut ExecOptions,
+    snapshot: &mut Snapshot,
+) -> (Status, bool) {
+    // Commands written by the operator in review are already approved
+    let mut queue: VecDeque<(Result<Command, ParseError>, bool)> =
+        parse_script(script).into_iter().map(|parsed| (parsed, false)).collect();
+    let mut status = Status::new();
+    let mut ws = Workspace::new(dir, options.dry_run.as_mut(), snapshot);
+
+    while let Some((parsed, approved)) = queue.pop_front() {
+        let mut cmd = match parsed {
+            Ok(cmd) => cmd,
+            Err(err) => {
+                eprintln!("❓ Parse error: {}", err);
+                status.add(CMD::ParseError {
+                    line: err.line,
+                    column: err.column,
+                    message: err.message,
+                });
+                if stops(&status, options.on_error) {
+                    return (status, true);
+                }
+                continue;
+            }
+        };
+
+        // Every path is confined to the project root before anything touches the disk
+        if let Some(path) = &cmd.file {
+            match ws.dir.resolve(path) {
+                Ok(relative) => {
+                    let relative = relative.to_string_lossy().to_string();
+                    cmd.file = Some(if relative.is_empty() { ".".to_string() } else { relative });
+                }
+                Err(rejected) => {
+                    eprintln!("⛔ {}", rejected);
+                    status.add(CMD::PathRejected {
+                        path: rejected.path,
+                        reason: rejected.reason,
+                    });
+                    if stops(&status, options.on_error) {
+                        return (status, true);
                     }
+                    continue;
                 }
-                Some(CreateType::File) => {
-                    //println!("📂 Opening file: {:?}", cmd.file);
-                    if let Some(path) = cmd.file.clone() {
-                        let full_path = if Path::new(&path).is_absolute() {
-                            PathBuf::from(&path)
-                        } else {
-                            dir.path.join(&path)
-                        };
-                        
-                        let file = File::read_from_path_with_parent(&full_path, &dir.path)?;
-                        let mut content_lines = Vec::new();
-                        for line in &file.data_line {
-                            let line_str = format!("{} | {}", line.number, line.data);
-                            println!("{}", line_str);
-                            content_lines.push(line_str);
-                        }
-                        let content = content_lines.join("\n");
-                        status.add(CMD::OpenFile { 
-                            path, 
-                            content 
-                        });
+            }
+        }
+
+        let decision = match cmd.command_type {
+            CommandType::Run => options.run_policy.check(&cmd.body),
+            _ => RunDecision::Allow,
+        };
+
+        // Denied RUN is refused below without bothering the operator
+        let mut reviewed = false;
+        if options.approval
+            && !approved
+            && !matches!(decision, RunDecision::Deny(_))
+            && let Some(preview) = preview(&ws, &cmd, &decision)
+        {
+            let review = match options.operator.as_mut() {
+                Some(operator) => operator.review(&cmd.body, &preview),
+                None => Review::Skip,
+            };
+            match review {
+                Review::Approve => reviewed = true,
+                Review::Skip => {
+                    println!("⏭️ Skipped: {}", cmd.body);
+                    status.add(CMD::Skipped { command: cmd.body.clone(), reason: "skipped by operator".to_string() });
+                    continue;
+                }
+                Review::Edit(replacement) => {
+                    println!("✏️ Replaced: {}", cmd.body);
+                    status.add(CMD::Skipped {
+                        command: cmd.body.clone(),
+                        reason: format!("replaced by operator with:\n{}", replacement),
+                    });
+                    for parsed in parse_script(&replacement).into_iter().rev() {
+                        queue.push_front((parsed, true));
                     }
+                    continue;
                 }
-                None => eprintln!("⚠️ OPEN missing type"),
-            },
+                Review::Abort => {
+                    println!("🛑 Aborted at: {}", cmd.body);
+                    status.add(CMD::Aborted { command: cmd.body.clone() });
+                    return (status, false);
+                }
+            }
+        }
 
-            CommandType::Edit => match cmd.create_type {
-                Some(CreateType::File) => {
-                    if let (Some(path), Some(line), Some(content)) =
-                        (cmd.file.clone(), cmd.line, cmd.content.clone())
-                    {
-                        let full_path = if Path::new(&path).is_absolute() {
-                            PathBuf::from(&path)
-                        } else {
-                            dir.path.join(&path)
-                        };
-                        
-                        //println!("📝 Editing file {:?} line {} => {}", full_path.display(), line, content);
-                        let mut file = File::read_from_path_with_parent(&full_path, &dir.path)?;
-                        file.edit_line(line, &content)?;
-                        dir.refresh_file(&full_path)?;
-                        status.add(CMD::EditFile { path, line, content });
+        let decision = match decision {
+            // Already confirmed in review
+            RunDecision::Ask(_) if reviewed => RunDecision::Allow,
+            // Nothing is executed in a dry run, no need to ask
+            RunDecision::Ask(_) if ws.dry_run.is_some() => RunDecision::Allow,
+            RunDecision::Ask(reason) => match options.operator.as_mut() {
+                Some(operator) => {
+                    if operator.confirm_run(&cmd.body, &reason) {
+                        RunDecision::Allow
+                    } else {
+                        RunDecision::Deny("refused by operator".to_string())
                     }
                 }
-                _ => eprintln!("⚠️ EDIT only supports FILE"),
+                None => RunDecision::Deny(format!("{}, no operator to ask", reason)),
             },
+            decision => decision,
+        };
+
+        match execute_command(&mut ws, &cmd, decision, options.bus.as_deref_mut(), &mut status) {
+            Ok(false) => {}
+            // CALLBACK ends the script
+            Ok(true) => return (status, false),
+            Err(err) => {
+                eprintln!("❌ {}: {}", cmd.body, err);
+                status.add(CMD::Error { command: cmd.body.clone(), message: err.to_string() });
+            }
+        }
+        // A PATCH without a single match or a rejected hunk fails without an io error
+        if stops(&status, options.on_error) {
+            return (status, true);
+        }
+    }
+
+    (status, false)
+}
+
+/// With on_error = stop the script ends at the first failed command, whatever kind of failure it is
+fn stops(status: &Status, on_error: OnError) -> bool {
+    on_error == OnError::Stop && status.running.last().is_some_and(CMD::is_failure)
+}
+
+/// Execute one checked command, true when it was CALLBACK
+fn execute_command(ws: &mut Workspace, cmd: &Command, decision: RunDecision, bus: Option<&mut (dyn Bus + '_)>, status: &mut Status) -> io::Result<bool> {
+    match cmd.command_type {
+        CommandType::Comments => {
+            println!("💬 {}", cmd.body);
+            status.add(CMD::Comments(cmd.body.clone()));
+        }
+
+        CommandType::Create => match cmd.create_type {
+            Some(CreateType::Dir) => {
+                //println!("📁 Creating dir: {:?}", cmd.file);
+                if let Some(name) = cmd.file.clone() {
+                    ws.create_dir(&name)?;
+                    status.add(CMD::CreateDir(name));
+                }
+            }
+            Some(CreateType::File) => {
+                //println!("📄 Creating file: {:?}", cmd.file);
+                if let Some(name) = cmd.file.clone() {
+                    ws.create_file(&name, cmd.lines.as_deref().unwrap_or_default())?;
+                    status.add(CMD::CreateFile(name));
+                }
+            }
+            None => eprintln!("⚠️ CREATE missing type"),
+        },
 
-            CommandType::Insert => match cmd.create_type {
-                Some(CreateType::File) => {
-                    if let (Some(path), Some(line), Some(content)) =
-                        (cmd.file.clone(), cmd.line, cmd.content.clone())
-                    {
-                        let full_path = if Path::new(&path).is_absolute() {
-                            PathBuf::from(&path)
-                        } else {
-                            dir.path.join(&path)
-                        };
-                        
-                        //println!("➕ Inserting into file {:?} at line {} => {}", full_path.display(), line, content);
-                        let mut file = File::read_from_path_with_parent(&full_path, &dir.path)?;
-                        file.insert_line(line, &content)?;
-                        dir.refresh_file(&full_path)?;
-                        status.add(CMD::InsertFile { path, line, content });
+        CommandType::Open => match cmd.create_type {
+            Some(CreateType::Dir) => {
+                //println!("📂 Opening dir: {:?}", cmd.file);
+                if let Some(path) = cmd.file.clone() {
+                    // Dry runs list the disk, changes in the overlay are not shown here
+                    let full_path = ws.dir.path.join(&path);
+                    
+                    let opened = Dir::read_from_path(&full_path)?;
+                    let content = opened.pretty_print();
+                    println!("{}", content);
+                    status.add(CMD::OpenDir { 
+                        path, 
+                        content 
+                    });
+                }
+            }
+            Some(CreateType::File) => {
+                //println!("📂 Opening file: {:?}", cmd.file);
+                if let Some(path) = cmd.file.clone() {
+                    let lines = ws.read(&path)?;
+                    let mut content_lines = Vec::new();
+                    for (i, line) in lines.iter().enumerate() {
+                        let line_str = format!("{} | {}", i + 1, line);
+                        println!("{}", line_str);
+                        content_lines.push(line_str);
                     }
+                    let content = content_lines.join("\n");
+                    status.add(CMD::OpenFile { 
+                        path, 
+                        content 
+                    });
                 }
-                _ => eprintln!("⚠️ INSERT only supports FILE"),
-            },
+            }
+            None => eprintln!("⚠️ OPEN missing type"),
+        },
 
-            CommandType::Delete => match cmd.create_type {
-                Some(CreateType::File) => {
-                    if let (Some(path), Some(line)) = (cmd.file.clone(), cmd.line) {
-                        let full_path = if Path::new(&path).is_absolute() {
-                            PathBuf::from(&path)
-                        } else {
-                            dir.path.join(&path)
-                        };
-                        
-                        //println!("🗑️  Deleting line {} from file {:?}", line, full_path.display());
-                        let mut file = File::read_from_path_with_parent(&full_path, &dir.path)?;
-                        file.delete_line(line)?;
-                        dir.refresh_file(&full_path)?;
-                        status.add(CMD::DeleteFile { path, line });
+        CommandType::Edit => match cmd.create_type {
+            Some(CreateType::File) => {
+                if let (Some(path), Some(line), Some(content)) =
+                    (cmd.file.clone(), cmd.line, cmd.content.clone())
+                {
+                    if let Some(edit) = line_edit(cmd) {
+                        ws.edit(&path, &edit)?;
                     }
+                    status.add(CMD::EditFile { path, line, content });
                 }
-                _ => eprintln!("⚠️ DELETE only supports FILE"),
-            },
+            }
+            _ => eprintln!("⚠️ EDIT only supports FILE"),
+        },
 
-            CommandType::Run => {
-                let command = cmd.body.clone();
-                println!("🚀 Running command: {}", command);
-                
-                match execute_shell_command(&command, &dir.path) {
-                    Ok((output, exit_code)) => {
-                        if !output.trim().is_empty() {
-                            println!("📤 Output:\n{}", output);
-                        }
-                        if exit_code != 0 {
-                            eprintln!("⚠️ Command exited with code: {}", exit_code);
-                        }
-                        status.add(CMD::Run { 
-                            command, 
-                            output, 
-                            exit_code 
-                        });
+        CommandType::Insert => match cmd.create_type {
+            Some(CreateType::File) => {
+                if let (Some(path), Some(line), Some(content)) =
+                    (cmd.file.clone(), cmd.line, cmd.content.clone())
+                {
+                    if let Some(edit) = line_edit(cmd) {
+                        ws.edit(&path, &edit)?;
+                    }
+                    status.add(CMD::InsertFile { path, line, content });
+                }
+            }
+            _ => eprintln!("⚠️ INSERT only supports FILE"),
+        },
+
+        CommandType::Delete => match cmd.create_type {
+            Some(CreateType::File) => {
+                if let (Some(path), Some(line)) = (cmd.file.clone(), cmd.line) {
+                    if let Some(edit) = line_edit(cmd) {
+                        ws.edit(&path, &edit)?;
                     }
-                    Err(e) => {
-                        eprintln!("❌ Failed to execute command: {}", e);
-                        status.add(CMD::Run { 
-                            command, 
-                            output: format!("Error: {}", e), 
-                            exit_code: -1 
-                        });
+                    match cmd.end_line {
+                        Some(end) => status.add(CMD::DeleteLines { path, start: line, end }),
+                        None => status.add(CMD::DeleteFile { path, line }),
                     }
                 }
             }
+            _ => eprintln!("⚠️ DELETE only supports FILE"),
+        },
 
-            CommandType::Callback => {
-                //println!("↩️ Callback triggered: {}", cmd.body);
-                status.add(CMD::Callback(cmd.body.clone()));
-                return Ok(status);
+        CommandType::Replace => match cmd.create_type {
+            Some(CreateType::File) => {
+                if let (Some(path), Some(start), Some(end), Some(content)) =
+                    (cmd.file.clone(), cmd.line, cmd.end_line, cmd.content.clone())
+                {
+                    if let Some(edit) = line_edit(cmd) {
+                        ws.edit(&path, &edit)?;
+                    }
+                    status.add(CMD::ReplaceLines { path, start, end, content });
+                }
+            }
+            _ => eprintln!("⚠️ REPLACE only supports FILE"),
+        },
+
+        CommandType::Patch => match cmd.create_type {
+            Some(CreateType::File) => {
+                if let (Some(path), Some(edit)) = (cmd.file.clone(), line_edit(cmd)) {
+                    let outcome = ws.edit(&path, &edit)?;
+                    match &outcome {
+                        PatchOutcome::Applied { .. } => {}
+                        PatchOutcome::NotFound => eprintln!("⚠️ PATCH {}: text not found", path),
+                        PatchOutcome::Ambiguous(n) => eprintln!("⚠️ PATCH {}: ambiguous ({} matches)", path, n),
+                    }
+                    status.add(CMD::Patch { path, outcome });
+                }
+            }
+            _ => eprintln!("⚠️ PATCH only supports FILE"),
+        },
+
+        CommandType::ApplyDiff => {
+            if let Some(diff) = cmd.content.clone() {
+                let files = apply_diff(ws, &diff)?;
+                for report in &files {
+                    let rejected = report.hunks.iter().filter(|h| matches!(h, HunkResult::Rejected { .. })).count();
+                    match &report.error {
+                        Some(err) => eprintln!("⚠️ DIFF {}: {}", report.path, err),
+                        None if rejected > 0 => eprintln!("⚠️ DIFF {}: {} hunk(s) rejected", report.path, rejected),
+                        None => println!("🧩 Applied diff to {}", report.path),
+                    }
+                }
+                status.add(CMD::ApplyDiff { files });
+            }
+        }
+
+        CommandType::Run => {
+            let command = cmd.body.clone();
+
+            if let RunDecision::Deny(reason) = decision {
+                eprintln!("⛔ RUN refused: {} ({})", command, reason);
+                status.add(CMD::Run {
+                    command,
+                    output: format!("Command refused by policy: {}. Do not retry it, use an allowed command or file commands.", reason),
+                    exit_code: -1,
+                });
+                return Ok(false);
+            }
+
+            if let Some(dry) = &mut ws.dry_run {
+                println!("🧪 Dry run, not running: {}", command);
+                dry.runs.push(command.clone());
+                status.add(CMD::Run {
+                    command,
+                    output: "Dry run: the command was not executed.".to_string(),
+                    exit_code: 0,
+                });
+                return Ok(false);
             }
 
-            CommandType::Unknown => {
-                eprintln!("❓ Unknown command: {}", cmd.body);
-                status.add(CMD::Unknown(cmd.body.clone()));
+            println!("🚀 Running command: {}", command);
+            
+            match execute_shell_command(&command, &ws.dir.path) {
+                Ok((output, exit_code)) => {
+                    if !output.trim().is_empty() {
+                        println!("📤 Output:\n{}", output);
+                    }
+                    if exit_code != 0 {
+                        eprintln!("⚠️ Command exited with code: {}", exit_code);
+                    }
+                    status.add(CMD::Run { 
+                        command, 
+                        output, 
+                        exit_code 
+                    });
+                }
+                Err(e) => {
+                    eprintln!("❌ Failed to execute command: {}", e);
+                    status.add(CMD::Run { 
+                        command, 
+                        output: format!("Error: {}", e), 
+                        exit_code: -1 
+                    });
+                }
+            }
+        }
+
+        CommandType::Callback => {
+            //println!("↩️ Callback triggered: {}", cmd.body);
+            status.add(CMD::Callback(cmd.body.clone()));
+            return Ok(true);
+        }
+
+        CommandType::Assign | CommandType::Accept | CommandType::Revise => {
+            return Err(io::Error::other("ASSIGN, ACCEPT and REVISE are manager commands"));
+        }
+
+        CommandType::Ask | CommandType::Notify => {
+            let Some(bus) = bus else {
+                return Err(io::Error::other("no other agents to talk to in this run"));
+            };
+            let to = cmd.target.clone().unwrap_or_default();
+            let text = cmd.content.clone().unwrap_or_default();
+
+            if let CommandType::Ask = cmd.command_type {
+                println!("❓ Asking {}: {}", to, text.lines().next().unwrap_or_default());
+                let answer = bus.ask(&to, &text).map_err(io::Error::other)?;
+                println!("💡 {} answered", to);
+                status.add(CMD::Asked { to, question: text, answer });
+            } else {
+                bus.notify(&to, &text).map_err(io::Error::other)?;
+                println!("📨 Message for {}", to);
+                status.add(CMD::Notified { to });
             }
         }
     }
 
-    Ok(status)
-}
\ No newline at end of file
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn trailing_input_is_reported_at_its_column() {
+        let err = parse_command(r#"EDIT FILE "a.py" LINE 3 PUT "x" extra"#).unwrap_err();
+        assert_eq!((err.column, err.message.as_str()), (33, "unexpected trailing input"));
+
+        // Positions come from the line of the script
+        let results = parse_script("COMMENTS \"ok\"\n\nOPEN FILE \"a.py\" \"b.py\"");
+        let err = results[1].as_ref().unwrap_err();
+        assert_eq!((err.line, err.column), (3, 18));
+    }
+
+    #[test]
+    fn block_of_a_broken_line_is_skipped() {
+        let results = parse_script("EDIT FILE \"C:\\x.py\" LINE 1 PUT <<<EOF\nRUN \"echo pwned\"\nEOF");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap_err().line, 1);
+
+        // Parsing goes on after the closing tag, both blocks of the line are skipped
+        let results = parse_script("PATCH FILE \"C:\\x.py\" FIND <<<OLD REPLACE <<< NEW\nRUN \"a\"\nOLD\nRUN \"b\"\nNEW\nCOMMENTS \"after\"");
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_err());
+        assert!(matches!(&results[1], Ok(Command { command_type: CommandType::Comments, body, .. }) if body == "after"));
+    }
+
+    #[test]
+    fn every_kind_of_failure_stops_the_script() {
+        let failures = [
+            ("parse error", r#"EDIT FILE "a.py" LINE x PUT "y""#),
+            ("path rejected", r#"EDIT FILE "../outside.py" LINE 1 PUT "y""#),
+            ("patch not found", r#"PATCH FILE "a.py" FIND "z = 1" REPLACE "z = 2""#),
+            ("patch ambiguous", r#"PATCH FILE "a.py" FIND " = 1" REPLACE " = 2""#),
+            ("rejected hunk", "APPLY DIFF <<<EOF\n--- a/a.py\n+++ b/a.py\n@@ -1,2 +1,2 @@\n-q = 9\n+q = 8\n w = 7\nEOF"),
+            ("diff file error", "APPLY DIFF <<<EOF\n--- a/missing.py\n+++ b/missing.py\n@@ -1 +1 @@\n-x\n+y\nEOF"),
+            ("command error", r#"EDIT FILE "missing.py" LINE 1 PUT "y""#),
+        ];
+        let root = std::env::temp_dir().join(format!("orchestra-stop-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project")).unwrap();
+        fs::write(root.join("project/a.py"), "x = 1\ny = 1\n").unwrap();
+        let mut dir = Dir::read_from_path(root.join("project")).unwrap();
+
+        for (kind, failing) in failures {
+            let script = format!("CREATE FILE \"new.py\" WITH \"n\"\n{}\nCREATE FILE \"after.py\" WITH \"a\"", failing);
+            let status = parse_and_execute(&mut dir, &script, &mut ExecOptions::default()).unwrap();
+            let n = status.running.len();
+            assert!(status.running[n - 2].is_failure(), "{}: {:?}", kind, status.running);
+            assert!(matches!(&status.running[n - 1], CMD::RolledBack { paths } if paths.contains(&"new.py".to_string())), "{}: {:?}", kind, status.running);
+            assert!(!root.join("project/new.py").exists(), "{}", kind);
+            assert!(!root.join("project/after.py").exists(), "{}", kind);
+            assert_eq!(fs::read_to_string(root.join("project/a.py")).unwrap(), "x = 1\ny = 1\n", "{}", kind);
+        }
+
+        // With continue the rest of the script still runs
+        let mut options = ExecOptions { on_error: OnError::Continue, ..ExecOptions::default() };
+        let script = format!("{}\nCREATE FILE \"after.py\" WITH \"a\"", failures[2].1);
+        parse_and_execute(&mut dir, &script, &mut options).unwrap();
+        assert!(root.join("project/after.py").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn missing_arguments_point_past_the_line() {
+        let err = parse_command("EDIT FILE \"a.py\" LINE").unwrap_err();
+        assert_eq!((err.column, err.message.as_str()), (22, "expected line number"));
+    }
+
+    /// Operator who gives the queued answers in order
+    struct Scripted(VecDeque<Review>);
+
+    impl Operator for Scripted {
+        fn confirm_run(&mut self, _command: &str, _reason: &str) -> bool {
+            false
+        }
+
+        fn review(&mut self, _action: &str, _preview: &str) -> Review {
+            self.0.pop_front().unwrap_or(Review::Abort)
+        }
+    }
+
+    #[test]
+    fn review_answers_approve_skip_edit_and_abort() {
+        let root = std::env::temp_dir().join(format!("orchestra-review-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut dir = Dir::read_from_path(&root).unwrap();
+
+        let script = [
+            r#"CREATE FILE "one.txt" WITH "1""#,
+            r#"CREATE FILE "two.txt" WITH "2""#,
+            r#"CREATE FILE "three.txt" WITH "3""#,
+            r#"CREATE FILE "four.txt" WITH "4""#,
+            r#"CREATE FILE "five.txt" WITH "5""#,
+        ]
+        .join("\n");
+        let answers = VecDeque::from([
+            Review::Approve,
+            Review::Skip,
+            Review::Edit(r#"CREATE FILE "edited.txt" WITH "e""#.to_string()),
+            Review::Abort,
+        ]);
+        let mut options = ExecOptions {
+            operator: Some(Box::new(Scripted(answers))),
+            approval: true,
+            ..ExecOptions::default()
+        };
+        let status = parse_and_execute(&mut dir, &script, &mut options).unwrap();
+
+        // The edited command runs without another review, nothing after the abort runs
+        let existing: Vec<bool> = ["one", "two", "three", "edited", "four", "five"]
+            .iter()
+            .map(|name| root.join(format!("{}.txt", name)).exists())
+            .collect();
+        assert_eq!(existing, [true, false, false, true, false, false]);
+        assert!(matches!(&status.running[..], [
+            CMD::CreateFile(one),
+            CMD::Skipped { reason: skipped, .. },
+            CMD::Skipped { reason: replaced, .. },
+            CMD::CreateFile(edited),
+            CMD::Aborted { command },
+        ] if one == "one.txt"
+            && skipped == "skipped by operator"
+            && replaced.starts_with("replaced by operator")
+            && edited == "edited.txt"
+            && command.contains("four.txt")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn empty_block_has_no_lines() {
+        let commands = parse_script("INSERT FILE \"a.py\" LINE 1 <<<EOF\nEOF\nINSERT FILE \"a.py\" LINE 1 <<<EOF\n\nEOF\nINSERT FILE \"a.py\" LINE 1 \"\"");
+        let lines: Vec<Vec<String>> = commands.into_iter().map(|c| c.unwrap().lines.unwrap()).collect();
+        assert_eq!(lines, [vec![], vec![String::new()], vec![String::new()]]);
+    }
+
+    #[test]
+    fn empty_block_changes_nothing() {
+        let root = std::env::temp_dir().join(format!("orchestra-empty-block-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.py"), "x = 1\n").unwrap();
+        let mut dir = Dir::read_from_path(&root).unwrap();
+
+        let script = "CREATE FILE \"empty.py\" WITH <<<EOF\nEOF\nINSERT FILE \"a.py\" LINE 1 <<<EOF\nEOF";
+        let status = parse_and_execute(&mut dir, script, &mut ExecOptions::default()).unwrap();
+        assert!(!status.running.iter().any(CMD::is_failure), "{:?}", status.running);
+        assert_eq!(fs::read_to_string(root.join("empty.py")).unwrap(), "");
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 1\n");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
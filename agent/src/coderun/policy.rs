@@ -0,0 +1,274 @@
+use serde::Deserialize;
+
+/// How RUN commands of an agent are treated
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RunMode {
+    /// Run commands which pass the allow/deny lists
+    #[default]
+    Allow,
+    /// Never run anything
+    Deny,
+    /// Run allowlisted commands, ask the operator for everything else
+    Ask,
+}
+
+/// What the executor does when a command of a script fails
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OnError {
+    /// Record the error and run the next commands, changes of the script stay
+    Continue,
+    /// Record the error, skip the rest of the script and roll its changes back
+    #[default]
+    Stop,
+}
+
+/// `[policy.run]` section of orc.toml
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct RunPolicy {
+    #[serde(default)]
+    pub mode: RunMode,
+    /// Allowed command prefixes, e.g. "python" or "cargo test"; empty means any command in allow mode
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Patterns which are refused wherever they appear, e.g. "sudo" or "rm -rf /"
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunDecision {
+    Allow,
+    Deny(String),
+    /// Operator has to confirm, the reason is shown to them
+    Ask(String),
+}
+
+impl RunPolicy {
+    /// Same lists with the agent's own mode
+    pub fn with_mode(&self, mode: Option<RunMode>) -> RunPolicy {
+        RunPolicy {
+            mode: mode.unwrap_or(self.mode),
+            ..self.clone()
+        }
+    }
+
+    pub fn check(&self, command: &str) -> RunDecision {
+        if self.mode == RunMode::Deny {
+            return RunDecision::Deny("RUN is disabled for this agent".to_string());
+        }
+
+        let line = ShellLine::parse(command);
+        if let Some(pattern) = self.deny.iter().find(|p| line.contains(p)) {
+            return RunDecision::Deny(format!("matches denied pattern \"{}\"", pattern));
+        }
+        // An empty allow list lets everything through in allow mode, in ask mode every command is asked about
+        if self.allow.is_empty() && self.mode == RunMode::Allow {
+            return RunDecision::Allow;
+        }
+
+        let reason = match line.special {
+            _ if self.allow.is_empty() => "the allow list is empty".to_string(),
+            Some(special) => format!("{} are never allowlisted", special),
+            None if self.is_allowlisted(&line) => return RunDecision::Allow,
+            None => format!("not in the allow list ({})", self.allow.join(", ")),
+        };
+        match self.mode {
+            RunMode::Ask => RunDecision::Ask(reason),
+            _ => RunDecision::Deny(reason),
+        }
+    }
+
+    /// Every command of the line has to start with the words of an allowed prefix
+    fn is_allowlisted(&self, line: &ShellLine) -> bool {
+        let prefixes: Vec<Vec<String>> = self.allow.iter().map(|p| ShellLine::parse(p).words()).collect();
+        line.commands
+            .iter()
+            .all(|words| prefixes.iter().any(|prefix| !prefix.is_empty() && words.starts_with(prefix)))
+    }
+}
+
+/// Shell line split into commands and their words, with quotes and escapes removed.
+/// Only good enough to check lists of prefixes and patterns, the line itself is run by `sh`.
+struct ShellLine {
+    commands: Vec<Vec<String>>,
+    /// First construct which can run or write something the words don't show
+    special: Option<&'static str>,
+}
+
+impl ShellLine {
+    fn parse(line: &str) -> Self {
+        let mut parsed = ShellLine { commands: Vec::new(), special: None };
+        let mut words: Vec<String> = Vec::new();
+        let mut word: Option<String> = None;
+        let mut chars = line.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => {
+                    let word = word.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => word.push(c),
+                            None => {
+                                parsed.note("unterminated quotes");
+                                break;
+                            }
+                        }
+                    }
+                }
+                '"' => {
+                    let word = word.get_or_insert_with(String::new);
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some('\\') => word.extend(chars.next()),
+                            // Substitutions still run inside double quotes
+                            Some(c @ '`') => {
+                                parsed.note("command substitutions");
+                                word.push(c);
+                            }
+                            Some('$') if chars.peek() == Some(&'(') => {
+                                parsed.note("command substitutions");
+                                word.push('$');
+                            }
+                            Some(c) => word.push(c),
+                            None => {
+                                parsed.note("unterminated quotes");
+                                break;
+                            }
+                        }
+                    }
+                }
+                '\\' => word.get_or_insert_with(String::new).extend(chars.next().filter(|&c| c != '\n')),
+                '`' => {
+                    parsed.note("command substitutions");
+                    word.get_or_insert_with(String::new).push(c);
+                }
+                '$' if chars.peek() == Some(&'(') => {
+                    parsed.note("command substitutions");
+                    word.get_or_insert_with(String::new).push(c);
+                }
+                '(' | ')' | '{' | '}' => {
+                    parsed.note("subshells and command groups");
+                    words.extend(word.take());
+                }
+                '<' | '>' => {
+                    parsed.note("redirects");
+                    words.extend(word.take());
+                }
+                // `&&`, `||`, `;`, pipes, newlines and `&` all start another command
+                ';' | '&' | '|' | '\n' => {
+                    words.extend(word.take());
+                    if !words.is_empty() {
+                        parsed.commands.push(std::mem::take(&mut words));
+                    }
+                }
+                c if c.is_whitespace() => words.extend(word.take()),
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        words.extend(word);
+        if !words.is_empty() {
+            parsed.commands.push(words);
+        }
+        parsed
+    }
+
+    fn note(&mut self, special: &'static str) {
+        self.special.get_or_insert(special);
+    }
+
+    fn words(self) -> Vec<String> {
+        self.commands.concat()
+    }
+
+    /// Words of the pattern appear in a row in one command, the first also as the name of a path like /usr/bin/sudo
+    fn contains(&self, pattern: &str) -> bool {
+        let pattern = ShellLine::parse(pattern).words();
+        let Some((first, rest)) = pattern.split_first() else {
+            return false;
+        };
+        self.commands.iter().any(|words| {
+            words.windows(pattern.len()).any(|window| {
+                let program = window[0].rsplit('/').next().unwrap_or_default();
+                (window[0] == *first || program == first) && window[1..] == *rest
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(mode: RunMode, allow: &[&str], deny: &[&str]) -> RunPolicy {
+        RunPolicy {
+            mode,
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn allowed(policy: &RunPolicy, command: &str) -> bool {
+        policy.check(command) == RunDecision::Allow
+    }
+
+    #[test]
+    fn every_chained_command_needs_an_allowed_prefix() {
+        let p = policy(RunMode::Allow, &["python", "cargo test"], &[]);
+        assert!(allowed(&p, "python a.py"));
+        assert!(allowed(&p, "python a.py && cargo test --all | python b.py; python c.py"));
+        assert!(!allowed(&p, "python a.py & rm -rf ~"));
+        assert!(!allowed(&p, "python a.py\nrm x"));
+        assert!(!allowed(&p, "python3 a.py"));
+        assert!(!allowed(&p, "cargo build"));
+        assert!(allowed(&p, "'python' \"a b.py\""));
+    }
+
+    #[test]
+    fn substitutions_subshells_and_redirects_are_not_allowlisted() {
+        let p = policy(RunMode::Allow, &["python", "echo"], &[]);
+        for command in ["echo $(rm x)", "echo \"`rm x`\"", "(rm x)", "python a.py > ~/.bashrc", "python < /etc/passwd", "echo 'open"] {
+            assert!(matches!(p.check(command), RunDecision::Deny(_)), "{}", command);
+        }
+        // Quoted text is not special
+        assert!(allowed(&p, "echo '$(x) > y'"));
+
+        let ask = policy(RunMode::Ask, &["python"], &[]);
+        assert_eq!(ask.check("python a.py > out.txt"), RunDecision::Ask("redirects are never allowlisted".to_string()));
+    }
+
+    #[test]
+    fn deny_patterns_match_whole_words() {
+        let p = policy(RunMode::Allow, &[], &["rm -rf /", "sudo"]);
+        assert!(!allowed(&p, "rm -rf '/'"));
+        assert!(!allowed(&p, "rm  -rf   /"));
+        assert!(!allowed(&p, "ls && rm -rf \"/\""));
+        assert!(allowed(&p, "rm -rf /tmp/build"));
+        assert!(!allowed(&p, "sudo ls"));
+        assert!(!allowed(&p, "/usr/bin/sudo ls"));
+        assert!(allowed(&p, "echo pseudo"));
+    }
+
+    #[test]
+    fn deny_wins_over_allow_and_ask() {
+        let p = policy(RunMode::Ask, &["curl"], &["curl"]);
+        assert!(matches!(p.check("curl x"), RunDecision::Deny(_)));
+        assert_eq!(policy(RunMode::Ask, &["python"], &[]).check("ls"), RunDecision::Ask("not in the allow list (python)".to_string()));
+        assert!(matches!(policy(RunMode::Deny, &[], &[]).check("ls"), RunDecision::Deny(_)));
+    }
+
+    #[test]
+    fn empty_allow_list_depends_on_the_mode() {
+        assert!(allowed(&policy(RunMode::Allow, &[], &[]), "rm -rf build"));
+        assert_eq!(policy(RunMode::Deny, &[], &[]).check("ls"), RunDecision::Deny("RUN is disabled for this agent".to_string()));
+
+        let ask = policy(RunMode::Ask, &[], &["sudo"]);
+        assert_eq!(ask.check("ls"), RunDecision::Ask("the allow list is empty".to_string()));
+        assert_eq!(ask.check("echo $(ls)"), RunDecision::Ask("the allow list is empty".to_string()));
+        assert!(matches!(ask.check("sudo ls"), RunDecision::Deny(_)));
+    }
+}
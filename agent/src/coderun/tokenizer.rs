@@ -0,0 +1,164 @@
+use std::fmt;
+
+/// One lexical unit of a DSL line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// Bare word: keyword, number or range like `3-7`
+    Word(String),
+    /// Quoted string with escapes already resolved
+    Str(String),
+    /// `<<<TAG` block marker, its lines are filled by parse_script from the next lines
+    Block { tag: String, body: Option<Vec<String>> },
+}
+
+/// Token with 1-based column of its first char, used for error reporting
+#[derive(Debug, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub column: usize,
+}
+
+/// Malformed DSL input with precise position
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Split one line into words, quoted strings and block markers.
+/// Strings support `\"`, `\\`, `\n` and `\t` escapes.
+pub fn tokenize(line: &str, line_number: usize) -> Result<Vec<Spanned>, ParseError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let error = |column: usize, message: String| ParseError {
+        line: line_number,
+        column,
+        message,
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == '"' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(error(column, "unterminated string".to_string())),
+                    Some('"') => {
+                        i += 1;
+                        break;
+                    }
+                    Some('\\') => {
+                        let escaped = match chars.get(i + 1) {
+                            Some('"') => '"',
+                            Some('\\') => '\\',
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(other) => {
+                                return Err(error(i + 1, format!("unknown escape `\\{}`", other)));
+                            }
+                            None => return Err(error(column, "unterminated string".to_string())),
+                        };
+                        value.push(escaped);
+                        i += 2;
+                    }
+                    Some(ch) => {
+                        value.push(*ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Spanned { token: Token::Str(value), column });
+            continue;
+        }
+
+        if chars[i..].starts_with(&['<', '<', '<']) {
+            i += 3;
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            if start == i {
+                return Err(error(column, "expected block tag after <<<".to_string()));
+            }
+            let tag: String = chars[start..i].iter().collect();
+            tokens.push(Spanned { token: Token::Block { tag, body: None }, column });
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '"' {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().collect();
+        tokens.push(Spanned { token: Token::Word(word), column });
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(line: &str) -> Vec<Token> {
+        tokenize(line, 1).unwrap().into_iter().map(|t| t.token).collect()
+    }
+
+    #[test]
+    fn escapes_are_resolved() {
+        assert_eq!(
+            tokens(r#"PUT "print(\"hi\")\n\tC:\\tmp""#),
+            vec![Token::Word("PUT".to_string()), Token::Str("print(\"hi\")\n\tC:\\tmp".to_string())]
+        );
+        assert_eq!(tokens(r#""a b"word"c""#), vec![
+            Token::Str("a b".to_string()),
+            Token::Word("word".to_string()),
+            Token::Str("c".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn columns_point_at_token_starts() {
+        let spanned = tokenize(r#"EDIT  "x y" <<< END"#, 1).unwrap();
+        let columns: Vec<usize> = spanned.iter().map(|t| t.column).collect();
+        assert_eq!(columns, vec![1, 7, 13]);
+        assert_eq!(spanned[2].token, Token::Block { tag: "END".to_string(), body: None });
+    }
+
+    #[test]
+    fn malformed_strings_report_their_column() {
+        let err = tokenize(r#"CREATE FILE "a.py"#, 4).unwrap_err();
+        assert_eq!((err.line, err.column, err.message.as_str()), (4, 13, "unterminated string"));
+
+        let err = tokenize(r#"PUT "a\qb""#, 1).unwrap_err();
+        assert_eq!((err.column, err.message.as_str()), (7, "unknown escape `\\q`"));
+
+        let err = tokenize(r#"PUT "a\"#, 1).unwrap_err();
+        assert_eq!((err.column, err.message.as_str()), (5, "unterminated string"));
+
+        let err = tokenize("INSERT <<< ", 1).unwrap_err();
+        assert_eq!((err.column, err.message.as_str()), (8, "expected block tag after <<<"));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+use std::io;
+
+use crate::filesystem::{Dir, File, LineEdit, Overlay, PatchOutcome, Snapshot};
+use super::diff::render_diff;
+
+/// State of a dry run: changes made in memory and the RUN commands which were not executed
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+    pub overlay: Overlay,
+    pub runs: Vec<String>,
+}
+
+impl DryRun {
+    pub fn new() -> Self {
+        DryRun::default()
+    }
+
+    /// Unified diff of every change plus the skipped RUN commands
+    pub fn report(&self) -> String {
+        let mut out = Vec::new();
+
+        for dir in self.overlay.created_dirs() {
+            out.push(format!("new directory {}", dir));
+        }
+        for change in self.overlay.changes() {
+            match (&change.old, &change.new) {
+                (None, _) => out.push(format!("new file {}", change.path)),
+                (_, None) => out.push(format!("deleted file {}", change.path)),
+                _ => {}
+            }
+            out.push(render_diff(
+                &change.path,
+                change.old.as_deref().unwrap_or_default(),
+                change.new.as_deref().unwrap_or_default(),
+            ));
+        }
+        if out.is_empty() {
+            out.push("no file changes".to_string());
+        }
+
+        if !self.runs.is_empty() {
+            out.push("RUN commands which were not executed:".to_string());
+            out.extend(self.runs.iter().map(|run| format!("$ {}", run)));
+        }
+        out.join("\n")
+    }
+}
+
+/// Diff of every file in the journal between its recorded original and the disk now
+pub fn journal_diff(journal: &Snapshot, dir: &Dir) -> String {
+    let lines = |bytes: Option<&[u8]>| -> Vec<String> {
+        bytes
+            .map(|b| String::from_utf8_lossy(b).lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    };
+
+    let mut out = Vec::new();
+    for (path, original) in journal.files() {
+        let current = fs::read(dir.path.join(path)).ok();
+        if original == current.as_deref() {
+            continue;
+        }
+        match (original, &current) {
+            (None, Some(_)) => out.push(format!("new file {}", path)),
+            (Some(_), None) => out.push(format!("deleted file {}", path)),
+            _ => {}
+        }
+        out.push(render_diff(path, &lines(original), &lines(current.as_deref())));
+    }
+    if out.is_empty() {
+        "no file changes".to_string()
+    } else {
+        out.join("\n")
+    }
+}
+
+/// File access of the executor, goes to the project on disk or to the dry-run overlay.
+/// Every file is recorded in `snapshot` before it is changed on disk.
+pub struct Workspace<'a> {
+    pub dir: &'a mut Dir,
+    pub dry_run: Option<&'a mut DryRun>,
+    pub snapshot: &'a mut Snapshot,
+}
+
+impl<'a> Workspace<'a> {
+    pub fn new(dir: &'a mut Dir, dry_run: Option<&'a mut DryRun>, snapshot: &'a mut Snapshot) -> Self {
+        Workspace { dir, dry_run, snapshot }
+    }
+
+    pub fn read(&self, path: &str) -> io::Result<Vec<String>> {
+        match &self.dry_run {
+            Some(dry) => dry.overlay.read(self.dir, path),
+            None => File::read_from_path_with_parent(self.dir.path.join(path), &self.dir.path).map(|f| f.lines()),
+        }
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        match &self.dry_run {
+            Some(dry) => dry.overlay.exists(self.dir, path),
+            None => self.dir.path.join(path).exists(),
+        }
+    }
+
+    pub fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        match &mut self.dry_run {
+            Some(dry) => {
+                dry.overlay.create_dir(path);
+                Ok(())
+            }
+            None => {
+                self.snapshot.record_dir(self.dir, path);
+                self.dir.create_dir(path)
+            }
+        }
+    }
+
+    /// New file, content None makes an empty one
+    pub fn create_file(&mut self, path: &str, lines: &[String]) -> io::Result<()> {
+        match &mut self.dry_run {
+            Some(dry) => {
+                dry.overlay.write(self.dir, path, lines.to_vec());
+                Ok(())
+            }
+            None => {
+                self.dir.claim(path)?;
+                self.snapshot.record(self.dir, path)?;
+                self.dir.create_file(path, lines)
+            }
+        }
+    }
+
+    pub fn remove_file(&mut self, path: &str) -> io::Result<()> {
+        match &mut self.dry_run {
+            Some(dry) => dry.overlay.remove(self.dir, path),
+            None => {
+                self.dir.claim(path)?;
+                self.snapshot.record(self.dir, path)?;
+                self.dir.remove_file(path)
+            }
+        }
+    }
+
+    /// Content before and after the edit and its outcome, nothing is written
+    pub fn simulate(&self, path: &str, edit: &LineEdit) -> io::Result<(Vec<String>, Vec<String>, PatchOutcome)> {
+        let old = self.read(path)?;
+        let mut file = File::from_lines(self.dir.path.join(path), &old);
+        let outcome = file.apply_edit(edit)?;
+        Ok((old, file.lines(), outcome))
+    }
+
+    /// Apply an edit to an existing file, it is written only when Applied
+    pub fn edit(&mut self, path: &str, edit: &LineEdit) -> io::Result<PatchOutcome> {
+        if self.dry_run.is_some() {
+            let (_, new, outcome) = self.simulate(path, edit)?;
+            if let (PatchOutcome::Applied { .. }, Some(dry)) = (&outcome, &mut self.dry_run) {
+                dry.overlay.write(self.dir, path, new);
+            }
+            return Ok(outcome);
+        }
+
+        let full_path = self.dir.path.join(path);
+        let mut file = File::read_from_path_with_parent(&full_path, &self.dir.path)?;
+        self.dir.claim(path)?;
+        self.snapshot.record(self.dir, path)?;
+        let outcome = file.apply(edit)?;
+        if let PatchOutcome::Applied { .. } = outcome {
+            self.dir.refresh_file(&full_path)?;
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::coderun::parser::{parse_and_execute, ExecOptions};
+    use super::*;
+
+    #[test]
+    fn dry_run_reports_changes_and_leaves_disk_alone() {
+        let root = std::env::temp_dir().join(format!("orchestra-dry-run-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.py"), "x = 1\nprint(x)\n").unwrap();
+        let mut dir = Dir::read_from_path(&root).unwrap();
+
+        let script = [
+            r#"EDIT FILE "a.py" LINE 1 PUT "x = 2""#,
+            r#"CREATE DIR "pkg""#,
+            r#"CREATE FILE "pkg/new.py" WITH "n = 0""#,
+            r#"RUN "python a.py""#,
+        ]
+        .join("\n");
+        let mut options = ExecOptions { dry_run: Some(DryRun::new()), ..ExecOptions::default() };
+        parse_and_execute(&mut dir, &script, &mut options).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 1\nprint(x)\n");
+        assert!(!root.join("pkg").exists());
+
+        let report = options.dry_run.unwrap().report();
+        for expected in ["new directory pkg", "new file pkg/new.py", "+n = 0", "-x = 1", "+x = 2", "$ python a.py"] {
+            assert!(report.contains(expected), "{:?} missing in:\n{}", expected, report);
+        }
+        assert_eq!(DryRun::new().report(), "no file changes");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,17 +1,103 @@
+use std::fs;
+use std::path::Path;
+
 use serde::Deserialize;
 
+use crate::coderun::policy::{OnError, RunMode, RunPolicy};
+use crate::error::OrchestraError;
+
 #[derive(Deserialize, Debug)]
 pub struct Config{
     pub project: ProjectConfig,
+    #[serde(default)]
+    pub llm: LlmConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
     pub employee: Vec<Employee>,
     pub manager: Vec<Manager>,
 }
 
+impl Config {
+    /// Read and check orc.toml
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, OrchestraError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|e| OrchestraError::Config(format!("cannot read {}: {}", path.display(), e)))?;
+        let config: Config = toml::from_str(&content)
+            .map_err(|e| OrchestraError::Config(format!("{}: {}", path.display(), e.message())))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), OrchestraError> {
+        for (i, employee) in self.employee.iter().enumerate() {
+            if employee.name.trim().is_empty() {
+                return Err(OrchestraError::Config(format!("[[employee]] #{} has an empty name", i + 1)));
+            }
+            if self.employee[..i].iter().any(|e| e.name == employee.name) {
+                return Err(OrchestraError::Config(format!("employee \"{}\" is defined twice", employee.name)));
+            }
+        }
+        for manager in &self.manager {
+            // `task` takes employee and manager names alike
+            if self.employee(&manager.name).is_ok() {
+                return Err(OrchestraError::Config(format!("\"{}\" is both an employee and a manager", manager.name)));
+            }
+            // A team holds employees and lower managers
+            if let Some(member) = manager.team.iter().find(|m| self.employee(m).is_err() && self.manager(m).is_none()) {
+                return Err(OrchestraError::Config(format!(
+                    "manager \"{}\" has unknown team member \"{}\"",
+                    manager.name, member
+                )));
+            }
+            self.check_hierarchy(manager, &mut vec![manager.name.as_str()])?;
+        }
+        Ok(())
+    }
+
+    /// Managers under a manager must not lead back to it, a task would be delegated forever
+    fn check_hierarchy<'a>(&'a self, manager: &'a Manager, chain: &mut Vec<&'a str>) -> Result<(), OrchestraError> {
+        for sub in manager.team.iter().filter_map(|m| self.manager(m)) {
+            if chain.contains(&sub.name.as_str()) {
+                chain.push(&sub.name);
+                return Err(OrchestraError::Config(format!("managers lead each other in a circle: {}", chain.join(" → "))));
+            }
+            chain.push(&sub.name);
+            self.check_hierarchy(sub, chain)?;
+            chain.pop();
+        }
+        Ok(())
+    }
+
+    pub fn manager(&self, name: &str) -> Option<&Manager> {
+        self.manager.iter().find(|m| m.name == name)
+    }
+
+    pub fn employee(&self, name: &str) -> Result<&Employee, OrchestraError> {
+        self.employee.iter().find(|e| e.name == name).ok_or_else(|| OrchestraError::UnknownEmployee {
+            name: name.to_string(),
+            known: self.employee.iter().map(|e| e.name.clone()).collect(),
+        })
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ProjectConfig {
   pub dir: String,
   pub ignore_dir: Vec<String>,
-  pub max_size: u64
+  pub max_size: u64,
+  /// Every agent works in its own git worktree, `merge` brings the branches back
+  #[serde(default)]
+  pub worktrees: bool,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct PolicyConfig {
+    #[serde(default)]
+    pub run: RunPolicy,
+    /// stop (default) | continue, what happens with a script after one of its commands fails
+    #[serde(default)]
+    pub on_error: OnError,
 }
 
 #[derive(Debug, Deserialize,Clone)]
@@ -19,6 +105,12 @@ pub struct Employee {
     pub dir: String,
     pub name: String,
     pub task: String,
+    /// Overrides [policy.run] mode for this employee
+    pub run_mode: Option<RunMode>,
+    /// Overrides [policy] on_error for this employee
+    pub on_error: Option<OnError>,
+    #[serde(flatten)]
+    pub llm: LlmConfig,
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,4 +119,106 @@ pub struct Manager {
     pub name: String,
     pub level: String,
     pub team: Vec<String>,
-}
\ No newline at end of file
+    /// Times a subtask may be sent back before the manager moves on, 2 by default
+    pub max_revisions: Option<u32>,
+    #[serde(flatten)]
+    pub llm: LlmConfig,
+}
+
+/// Model settings, used as project default in [llm] and as per-employee override in [[employee]]
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct LlmConfig {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub base_url: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub api_key_env: Option<String>,
+    /// Request timeout, 120 by default
+    pub timeout_secs: Option<u64>,
+    /// Retries of transient failures (rate limits, overload, 5xx, timeouts), 4 by default
+    pub max_retries: Option<u32>,
+    /// First backoff delay, doubled on every retry, 1000 by default
+    pub retry_base_ms: Option<u64>,
+    /// Upper bound of the backoff delay, 60000 by default
+    pub retry_max_ms: Option<u64>,
+    /// Context window in tokens, known models get theirs from the model name
+    pub context_tokens: Option<usize>,
+    /// Mock provider only: file with canned responses, one per turn
+    pub responses: Option<String>,
+    /// Mock provider only: file where every received request is appended as JSON line
+    pub record: Option<String>,
+}
+
+impl LlmConfig {
+    /// Fill every unset field from the fallback (usually the project-wide [llm] table)
+    pub fn or(&self, fallback: &LlmConfig) -> LlmConfig {
+        LlmConfig {
+            provider: self.provider.clone().or_else(|| fallback.provider.clone()),
+            model: self.model.clone().or_else(|| fallback.model.clone()),
+            base_url: self.base_url.clone().or_else(|| fallback.base_url.clone()),
+            temperature: self.temperature.or(fallback.temperature),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            api_key_env: self.api_key_env.clone().or_else(|| fallback.api_key_env.clone()),
+            timeout_secs: self.timeout_secs.or(fallback.timeout_secs),
+            max_retries: self.max_retries.or(fallback.max_retries),
+            retry_base_ms: self.retry_base_ms.or(fallback.retry_base_ms),
+            retry_max_ms: self.retry_max_ms.or(fallback.retry_max_ms),
+            context_tokens: self.context_tokens.or(fallback.context_tokens),
+            responses: self.responses.clone().or_else(|| fallback.responses.clone()),
+            record: self.record.clone().or_else(|| fallback.record.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, OrchestraError> {
+        let config: Config = toml::from_str(text).map_err(|e| OrchestraError::Config(e.message().to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    const PROJECT: &str = "[project]\ndir = \"./project\"\nignore_dir = []\nmax_size = 1024\n";
+
+    #[test]
+    fn shipped_config_loads() {
+        let config = Config::load(concat!(env!("CARGO_MANIFEST_DIR"), "/orc.toml")).unwrap();
+        assert!(!config.employee.is_empty());
+    }
+
+    #[test]
+    fn manager_may_lead_managers() {
+        let config = parse(&format!(
+            "{}[[employee]]\ndir = \"a\"\nname = \"Alex\"\ntask = \"dev\"\n\
+            [[manager]]\ndir = \"j\"\nname = \"John\"\nlevel = \"senior\"\nteam = [\"Alex\"]\n\
+            [[manager]]\ndir = \"e\"\nname = \"Emma\"\nlevel = \"lead\"\nteam = [\"John\"]\n",
+            PROJECT
+        ));
+        assert!(config.is_ok());
+    }
+
+    #[test]
+    fn unknown_member_is_rejected() {
+        let err = parse(&format!(
+            "{}[[employee]]\ndir = \"a\"\nname = \"Alex\"\ntask = \"dev\"\n[[manager]]\ndir = \"j\"\nname = \"John\"\nlevel = \"senior\"\nteam = [\"Nobody\"]\n",
+            PROJECT
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown team member \"Nobody\""), "{}", err);
+    }
+
+    #[test]
+    fn circle_of_managers_is_rejected() {
+        let err = parse(&format!(
+            "{}[[employee]]\ndir = \"a\"\nname = \"Alex\"\ntask = \"dev\"\n\
+            [[manager]]\ndir = \"j\"\nname = \"John\"\nlevel = \"senior\"\nteam = [\"Emma\"]\n\
+            [[manager]]\ndir = \"e\"\nname = \"Emma\"\nlevel = \"lead\"\nteam = [\"John\"]\n",
+            PROJECT
+        ))
+        .unwrap_err();
+        assert!(err.to_string().contains("circle"), "{}", err);
+    }
+}
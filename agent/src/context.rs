@@ -0,0 +1,267 @@
+use crate::config::LlmConfig;
+use crate::provider::Message;
+use crate::provider::{anthropic, openai};
+
+/// Header of the message which replaces the summarized turns
+const SUMMARY_HEADER: &str = "=== Summary of earlier turns ===";
+/// Messages at the end of the history which are never compacted: the last script and its results
+const KEEP_RECENT: usize = 2;
+/// Lines of an old message which survive truncation, from the start and from the end
+const HEAD_LINES: usize = 20;
+const TAIL_LINES: usize = 5;
+/// Longer lines of old messages are cut, a minified file is one line
+const MAX_LINE_CHARS: usize = 300;
+/// Lines of the summary, older ones are dropped first
+const MAX_SUMMARY_LINES: usize = 200;
+/// First line of every entry of the execution feedback, only these go into the summary
+const FEEDBACK_MARKERS: &[&str] = &[
+    "💬", "📁", "📄", "📂", "✏️", "➕", "🗑️", "🔁", "🩹", "🧩", "⛔", "⏭️", "🛑", "❌", "↩️", "🚀", "❓", "💡", "📨",
+];
+
+/// Rough token count without a tokenizer: about 4 ASCII characters per token, other scripts cost a token per character
+pub fn estimate_tokens(text: &str) -> usize {
+    let (ascii, other): (usize, usize) = text.chars().fold((0, 0), |(a, o), c| if c.is_ascii() { (a + 1, o) } else { (a, o + 1) });
+    ascii.div_ceil(4) + other
+}
+
+/// Tokens of the whole conversation, with the per-message overhead of the chat formats
+pub fn history_tokens(history: &[Message]) -> usize {
+    history.iter().map(|m| estimate_tokens(&m.content) + 4).sum()
+}
+
+/// Context window of known models, unknown ones get a conservative 32k
+pub fn context_window(model: &str) -> usize {
+    let model = model.to_lowercase();
+    let table: &[(&str, usize)] = &[
+        ("claude", 200_000),
+        ("gpt-4.1", 1_047_576),
+        ("gpt-5", 400_000),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4", 8_192),
+        ("gpt-3.5", 16_385),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("o4", 200_000),
+    ];
+    table
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, window)| *window)
+        .unwrap_or(32_768)
+}
+
+/// What one compaction did, for the agent log
+#[derive(Debug)]
+pub struct Compaction {
+    pub before: usize,
+    pub after: usize,
+    pub truncated: usize,
+    pub summarized: usize,
+}
+
+/// Tokens the conversation may take, the rest of the window is left for the answer
+#[derive(Debug, Clone, Copy)]
+pub struct ContextBudget {
+    pub limit: usize,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        ContextBudget { limit: 128_000 - 4_096 }
+    }
+}
+
+impl ContextBudget {
+    /// Window from `context_tokens` or the model name, minus `max_tokens` for the answer
+    pub fn from_config(config: &LlmConfig) -> Self {
+        let window = config.context_tokens.unwrap_or_else(|| {
+            let model = match (&config.model, config.provider.as_deref()) {
+                (Some(model), _) => model.as_str(),
+                (None, Some("anthropic")) => anthropic::DEFAULT_MODEL,
+                (None, _) => openai::DEFAULT_MODEL,
+            };
+            context_window(model)
+        });
+        let reserve = config.max_tokens.unwrap_or(4_096) as usize;
+        // A misconfigured tiny window still leaves room for the system prompt and task
+        ContextBudget { limit: window.saturating_sub(reserve).max(1_024) }
+    }
+
+    /// Shrink the history until it fits: first old output is truncated, then the oldest turns are summarized.
+    /// System prompt and task (everything up to the first user message) are always kept as they are.
+    pub fn compact(&self, history: &mut Vec<Message>) -> Option<Compaction> {
+        let before = history_tokens(history);
+        if before <= self.limit {
+            return None;
+        }
+
+        let pinned = history.iter().position(|m| m.role == "user").map_or(history.len(), |i| i + 1);
+        let mut summary = match history.get(pinned) {
+            Some(m) if m.role == "user" && m.content.starts_with(SUMMARY_HEADER) => {
+                let old = history.remove(pinned);
+                old.content.lines().skip(1).map(str::to_string).collect()
+            }
+            _ => Vec::new(),
+        };
+        let mut truncated = 0;
+        let mut summarized = 0;
+        let over = |history: &[Message], summary: &[String]| {
+            history_tokens(history) + estimate_tokens(&summary.join("\n")) + 8 > self.limit
+        };
+
+        // Old output first, the oldest messages are the least useful
+        let recent = history.len().saturating_sub(KEEP_RECENT).max(pinned);
+        for i in pinned..recent {
+            if !over(history, &summary) {
+                break;
+            }
+            if let Some(short) = truncate(&history[i].content) {
+                history[i].content = short;
+                truncated += 1;
+            }
+        }
+
+        // Then whole turns, a script and its results, go into the summary
+        while over(history, &summary) && history.len() > pinned + KEEP_RECENT {
+            let message = history.remove(pinned);
+            summary.extend(summarize(&message));
+            if message.role == "assistant" {
+                summarized += 1;
+            }
+        }
+        if summary.len() > MAX_SUMMARY_LINES {
+            summary.drain(..summary.len() - MAX_SUMMARY_LINES);
+        }
+
+        // Consecutive user messages are merged by the backends, so the turn order stays valid
+        if !summary.is_empty() {
+            let content = format!("{}\n{}", SUMMARY_HEADER, summary.join("\n"));
+            history.insert(pinned, Message::new("user", content));
+        }
+
+        // The last turn alone is still too big, cut it as well
+        for i in pinned..history.len() {
+            if history_tokens(history) <= self.limit {
+                break;
+            }
+            if let Some(short) = truncate(&history[i].content) {
+                history[i].content = short;
+                truncated += 1;
+            }
+        }
+
+        Some(Compaction { before, after: history_tokens(history), truncated, summarized })
+    }
+}
+
+/// Head and tail of a long message with cut long lines, None when it is short already
+fn truncate(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let long_line = lines.iter().any(|l| l.chars().count() > MAX_LINE_CHARS);
+    if lines.len() <= HEAD_LINES + TAIL_LINES + 1 && !long_line {
+        return None;
+    }
+
+    let cut = |line: &str| -> String {
+        if line.chars().count() > MAX_LINE_CHARS {
+            format!("{} …", line.chars().take(MAX_LINE_CHARS).collect::<String>())
+        } else {
+            line.to_string()
+        }
+    };
+
+    if lines.len() <= HEAD_LINES + TAIL_LINES + 1 {
+        return Some(lines.iter().map(|l| cut(l)).collect::<Vec<_>>().join("\n"));
+    }
+    let mut out: Vec<String> = lines[..HEAD_LINES].iter().map(|l| cut(l)).collect();
+    out.push(format!("… [{} lines of old output truncated]", lines.len() - HEAD_LINES - TAIL_LINES));
+    out.extend(lines[lines.len() - TAIL_LINES..].iter().map(|l| cut(l)));
+    Some(out.join("\n"))
+}
+
+/// Summary lines of one message: COMMENTS and CALLBACK of a script, the first line of every result
+fn summarize(message: &Message) -> Vec<String> {
+    let short = |line: &str| line.chars().take(160).collect::<String>();
+
+    if message.role == "assistant" {
+        let notes: Vec<String> = message
+            .content
+            .lines()
+            .filter_map(|l| l.strip_prefix("COMMENTS ").or_else(|| l.strip_prefix("CALLBACK ")))
+            .map(|l| short(l.trim().trim_matches('"')))
+            .collect();
+        let notes = if notes.is_empty() { "(no comments)".to_string() } else { notes.join("; ") };
+        return vec![format!("- Script: {}", notes)];
+    }
+
+    message
+        .content
+        .lines()
+        .filter(|l| FEEDBACK_MARKERS.iter().any(|m| l.starts_with(m)))
+        .map(|l| format!("  {}", short(l)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// System prompt, task and `turns` scripts each with a long result
+    fn conversation(turns: usize) -> Vec<Message> {
+        let mut history = vec![Message::new("system", "rules"), Message::new("user", "task")];
+        for i in 0..turns {
+            history.push(Message::new("assistant", format!("COMMENTS \"step {}\"\nRUN \"cargo test\"", i)));
+            let output: Vec<String> = (0..100).map(|n| format!("test case_{} ... ok", n)).collect();
+            history.push(Message::new("user", format!("🚀 Run: cargo test\n{}", output.join("\n"))));
+        }
+        history
+    }
+
+    #[test]
+    fn tokens_are_estimated_per_script() {
+        assert_eq!(estimate_tokens("abcdefgh"), 2);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("привет"), 6);
+        assert_eq!(context_window("GPT-4o-mini"), 128_000);
+        assert_eq!(context_window("local-llama"), 32_768);
+
+        let config = LlmConfig { model: Some("claude-sonnet-4-5".to_string()), max_tokens: Some(8_000), ..LlmConfig::default() };
+        assert_eq!(ContextBudget::from_config(&config).limit, 192_000);
+    }
+
+    #[test]
+    fn history_under_the_limit_is_untouched() {
+        let mut history = conversation(2);
+        let before = history.clone();
+        assert!(ContextBudget { limit: 100_000 }.compact(&mut history).is_none());
+        assert_eq!(history.len(), before.len());
+    }
+
+    #[test]
+    fn old_turns_are_truncated_then_summarized() {
+        let mut history = conversation(10);
+        let last = history[history.len() - 2..].to_vec();
+        let budget = ContextBudget { limit: 800 };
+        let compaction = budget.compact(&mut history).unwrap();
+
+        assert!(compaction.after <= budget.limit, "{:?}", compaction);
+        assert!(compaction.truncated > 0 && compaction.summarized > 0, "{:?}", compaction);
+        assert_eq!(history[0].content, "rules");
+        assert_eq!(history[1].content, "task");
+        assert!(history[2].content.starts_with(SUMMARY_HEADER));
+        assert!(history[2].content.contains("- Script: step 0"));
+        assert!(history[2].content.contains("  🚀 Run: cargo test"));
+
+        // The last script and its results stay as they were
+        let n = history.len();
+        assert_eq!(history[n - 2].content, last[0].content);
+        assert_eq!(history[n - 1].content, last[1].content);
+
+        // A second compaction extends the same summary
+        history.extend(conversation(3).into_iter().skip(2));
+        budget.compact(&mut history).unwrap();
+        assert_eq!(history.iter().filter(|m| m.content.starts_with(SUMMARY_HEADER)).count(), 1);
+        assert!(history[2].content.contains("- Script: step 0"));
+    }
+}
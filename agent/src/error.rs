@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Failures which end a task or the start of the shell, reported to the operator instead of panicking
+#[derive(Debug)]
+pub enum OrchestraError {
+    /// orc.toml is missing, unreadable or inconsistent
+    Config(String),
+    /// `task` named an agent which is not an [[employee]]
+    UnknownEmployee { name: String, known: Vec<String> },
+    /// `resume` got an id without a stored session, or the file is broken
+    Session(String),
+    /// Manager answered without a usable plan
+    Manager(String),
+    /// Model backend could not be built or failed after its retries
+    Provider(Box<dyn Error>),
+    Io(io::Error),
+}
+
+impl fmt::Display for OrchestraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrchestraError::Config(message) => write!(f, "config error: {}", message),
+            OrchestraError::UnknownEmployee { name, known } => {
+                write!(f, "unknown employee \"{}\", known: {}", name, known.join(", "))
+            }
+            OrchestraError::Session(message) => write!(f, "session error: {}", message),
+            OrchestraError::Manager(message) => write!(f, "manager error: {}", message),
+            OrchestraError::Provider(err) => write!(f, "model provider failed: {}", err),
+            OrchestraError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl Error for OrchestraError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OrchestraError::Provider(err) => Some(err.as_ref()),
+            OrchestraError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for OrchestraError {
+    fn from(err: io::Error) -> Self {
+        OrchestraError::Io(err)
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Files claimed by agents which run at the same time, shared by all agents of one run.
+/// An agent claims a file on its first write and keeps it until it finishes.
+#[derive(Clone, Default)]
+pub struct FileLocks {
+    owners: Arc<Mutex<HashMap<PathBuf, String>>>,
+}
+
+impl FileLocks {
+    /// Handle which claims files in the name of one agent
+    pub fn holder(&self, owner: &str) -> LockHolder {
+        LockHolder { locks: self.clone(), owner: owner.to_string() }
+    }
+}
+
+/// One agent's access to the shared locks
+#[derive(Clone)]
+pub struct LockHolder {
+    locks: FileLocks,
+    owner: String,
+}
+
+impl fmt::Debug for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LockHolder({})", self.owner)
+    }
+}
+
+impl LockHolder {
+    /// Claim a file, Err with the owner when another agent holds it
+    pub fn claim(&self, path: PathBuf) -> Result<(), String> {
+        let mut owners = self.locks.owners.lock().unwrap_or_else(|e| e.into_inner());
+        match owners.get(&path) {
+            Some(owner) if *owner != self.owner => Err(owner.clone()),
+            Some(_) => Ok(()),
+            None => {
+                owners.insert(path, self.owner.clone());
+                Ok(())
+            }
+        }
+    }
+
+    /// Give back every file of this agent
+    pub fn release(&self) {
+        let mut owners = self.locks.owners.lock().unwrap_or_else(|e| e.into_inner());
+        owners.retain(|_, owner| *owner != self.owner);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn second_agent_waits_for_the_release() {
+        let locks = FileLocks::default();
+        let (alex, lisa) = (locks.holder("Alex"), locks.holder("Lisa"));
+
+        alex.claim(PathBuf::from("src/a.py")).unwrap();
+        alex.claim(PathBuf::from("src/a.py")).unwrap();
+        assert_eq!(lisa.claim(PathBuf::from("src/a.py")), Err("Alex".to_string()));
+        lisa.claim(PathBuf::from("src/b.py")).unwrap();
+
+        alex.release();
+        lisa.claim(PathBuf::from("src/a.py")).unwrap();
+        assert_eq!(alex.claim(PathBuf::from("src/b.py")), Err("Lisa".to_string()));
+    }
+
+    #[test]
+    fn only_one_of_many_threads_gets_a_file() {
+        let locks = FileLocks::default();
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let holder = locks.holder(&format!("agent-{}", i));
+                thread::spawn(move || holder.claim(PathBuf::from("main.py")).is_ok())
+            })
+            .collect();
+        let winners = handles.into_iter().map(|h| h.join().unwrap()).filter(|won| *won).count();
+        assert_eq!(winners, 1);
+    }
+}
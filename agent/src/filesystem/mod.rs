@@ -1,6 +1,16 @@
+use std::fmt;
 use std::fs;
 use std::io::{self, BufRead, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+
+mod lock;
+mod overlay;
+mod snapshot;
+pub use lock::{FileLocks, LockHolder};
+pub use overlay::Overlay;
+pub use snapshot::Snapshot;
+
+use serde::{Deserialize, Serialize};
 
 //The main structure for working with the directory, this structure acts as a root, you can use CRUD methods on this structure 
 #[derive(Debug, Clone)]
@@ -11,6 +21,8 @@ pub struct Dir {
     pub subdirs: Vec<Dir>,
     pub ignore: Vec<String>,
     pub ignore_size: Option<u64>,
+    /// Set on the root when several agents work on the project at once
+    pub locks: Option<LockHolder>,
 }
 
 ///Mark for operation which file, 
@@ -25,6 +37,47 @@ pub struct File {
     pub size: u64,
 }
 
+/// Path from a DSL command which points outside the project root
+#[derive(Debug, Clone)]
+pub struct PathRejected {
+    pub path: String,
+    pub reason: String,
+}
+
+impl fmt::Display for PathRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "path \"{}\" rejected: {}", self.path, self.reason)
+    }
+}
+
+/// One change of file content, see File::apply_edit
+#[derive(Debug, Clone)]
+pub enum LineEdit {
+    /// Replace one line with a block, missing lines are created
+    Edit { line: usize, lines: Vec<String> },
+    /// Insert a block before line, next lines shift down
+    Insert { line: usize, lines: Vec<String> },
+    /// Replace the existing range start..=end with a block
+    Replace { start: usize, end: usize, lines: Vec<String> },
+    /// Delete the existing range start..=end
+    Delete { start: usize, end: usize },
+    /// Replace the exact text `find`, which must occur once
+    Patch { find: String, replace: String },
+    /// Replace the whole content
+    Set(Vec<String>),
+}
+
+/// Result of a change of file content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PatchOutcome {
+    /// Change applied, first changed line
+    Applied { line: usize },
+    /// PATCH text not found, nothing was changed
+    NotFound,
+    /// Several PATCH matches, nothing was changed
+    Ambiguous(usize),
+}
+
 ///Contains information about the term, we unload the lines into memory using this structure, yes, this is not very good in relation to memory, but we always have quick access to the content
 #[derive(Debug, Clone)]
 pub struct Line {
@@ -109,6 +162,7 @@ impl Dir {
             subdirs: Vec::new(),
             ignore: ignore.clone(),
             ignore_size,
+            locks: None,
         };
 
         for entry in fs::read_dir(path_ref)? {
@@ -128,12 +182,11 @@ impl Dir {
                     Dir::read_from_path_with_options(&entry_path, ignore.clone(), ignore_size)?;
                 dir.subdirs.push(subdir);
             } else if entry_path.is_file() {
-                if let Some(max_size) = ignore_size {
-                    if let Ok(metadata) = fs::metadata(&entry_path) {
-                        if metadata.len() > max_size {
-                            continue;
-                        }
-                    }
+                if let Some(max_size) = ignore_size
+                    && let Ok(metadata) = fs::metadata(&entry_path)
+                    && metadata.len() > max_size
+                {
+                    continue;
                 }
 
                 let file = File::read_from_path_with_parent(&entry_path, &dir.path)?;
@@ -147,21 +200,46 @@ impl Dir {
     pub fn create_dir(&mut self, name: &str) -> io::Result<()> {
         let new_path = self.path.join(name);
         fs::create_dir_all(&new_path)?;
-        *self = Dir::read_from_path_with_options(&self.path, self.ignore.clone(), self.ignore_size)?;
-        Ok(())
+        self.reload()
     }
 
-    /// Creates a new file and refreshes the data in the Dir structure.
-    pub fn create_file(&mut self, name: &str, content: Option<&str>) -> io::Result<()> {
+    /// Creates a new file with the lines, none gives an empty file, and refreshes the data in the Dir structure.
+    pub fn create_file(&mut self, name: &str, lines: &[String]) -> io::Result<()> {
         let new_file = self.path.join(name);
+        if let Some(parent) = new_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
         let mut file = fs::File::create(&new_file)?;
-        if let Some(text) = content {
-            writeln!(file, "{}", text)?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
         }
+        self.reload()
+    }
+
+    /// Removes a file and refreshes the data in the Dir structure.
+    pub fn remove_file(&mut self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.path.join(name))?;
+        self.reload()
+    }
+
+    /// Read the whole tree from disk again, keeping ignore settings
+    pub fn reload(&mut self) -> io::Result<()> {
+        let locks = self.locks.take();
         *self = Dir::read_from_path_with_options(&self.path, self.ignore.clone(), self.ignore_size)?;
+        self.locks = locks;
         Ok(())
     }
 
+    /// Claim a file before writing it, fails while another agent is changing the same file
+    pub fn claim(&self, name: &str) -> io::Result<()> {
+        match &self.locks {
+            Some(locks) => locks.claim(self.path.join(name)).map_err(|owner| {
+                io::Error::other(format!("{} is being changed by {}, work on other files or try later", name, owner))
+            }),
+            None => Ok(()),
+        }
+    }
+
     ///Synchronize the file system for a single file, using its full path.
     pub fn refresh_file(&mut self, file_path: &Path) -> io::Result<()> {
         //Find file in current dir patch 
@@ -181,6 +259,66 @@ impl Dir {
         Ok(())
     }
 
+    /// Resolve a path from the DSL against the project root and return it relative to the root.
+    /// `..` is normalized and existing parts are canonicalized, so absolute paths, `../` and symlinks
+    /// leading outside the root are rejected.
+    pub fn resolve(&self, requested: &str) -> Result<PathBuf, PathRejected> {
+        let reject = |reason: String| PathRejected {
+            path: requested.to_string(),
+            reason,
+        };
+
+        let root = fs::canonicalize(&self.path)
+            .map_err(|e| reject(format!("project root unavailable: {}", e)))?;
+
+        let joined = if Path::new(requested).is_absolute() {
+            PathBuf::from(requested)
+        } else {
+            root.join(requested)
+        };
+
+        // Lexical normalization, `..` never goes above the filesystem root
+        let mut normalized = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                other => normalized.push(other),
+            }
+        }
+
+        // Canonicalize the longest existing prefix to follow symlinks, the rest does not exist yet
+        let mut existing = normalized.clone();
+        let mut rest = Vec::new();
+        loop {
+            match fs::symlink_metadata(&existing) {
+                Ok(meta) if meta.file_type().is_symlink() && !existing.exists() => {
+                    return Err(reject("dangling symlink".to_string()));
+                }
+                Ok(_) => break,
+                Err(_) => match (existing.file_name().map(|n| n.to_os_string()), existing.parent()) {
+                    (Some(name), Some(parent)) => {
+                        rest.push(name);
+                        existing = parent.to_path_buf();
+                    }
+                    _ => break,
+                },
+            }
+        }
+        let mut resolved = fs::canonicalize(&existing)
+            .map_err(|e| reject(e.to_string()))?;
+        for name in rest.iter().rev() {
+            resolved.push(name);
+        }
+
+        match resolved.strip_prefix(&root) {
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => Err(reject(format!("outside of project root {}", self.path.display()))),
+        }
+    }
+
     /// Set ignore item
     #[allow(dead_code)]
     pub fn set_ignore(&mut self, ignore: Vec<String>) -> io::Result<()> {
@@ -199,7 +337,8 @@ impl Dir {
         Ok(())
     }
 
-    /// 
+    /// Set max size of file which we load in memory
+    #[allow(dead_code)]
     pub fn set_ignore_size(&mut self, max_size: Option<u64>) -> io::Result<()> {
         self.ignore_size = max_size;
         *self = Dir::read_from_path_with_options(&self.path, self.ignore.clone(), self.ignore_size)?;
@@ -275,29 +414,9 @@ impl File {
 
     /// Изменяет строку по номеру и сохраняет файл
     /// Если строки не существует, создаёт её
+    #[allow(dead_code)]
     pub fn edit_line(&mut self, line_number: usize, new_text: &str) -> io::Result<()> {
-        // Если файл пустой или строка больше текущего размера - добавляем недостающие строки
-        while self.data_line.len() < line_number {
-            self.data_line.push(Line {
-                number: self.data_line.len() + 1,
-                data: String::new(),
-            });
-        }
-        
-        // Теперь редактируем нужную строку
-        if let Some(line) = self.data_line.iter_mut().find(|l| l.number == line_number) {
-            line.data = new_text.to_string();
-        } else {
-            // Этот случай не должен произойти после цикла выше, но на всякий случай
-            self.data_line.push(Line {
-                number: line_number,
-                data: new_text.to_string(),
-            });
-        }
-        
-        self.save()?;
-        self.reload()?;
-        Ok(())
+        self.edit_lines(line_number, &[new_text.to_string()])
     }
 
     /// Добавляет новую строку в конец файла
@@ -341,64 +460,207 @@ impl File {
 }
 
 impl File {
+    /// Apply a change to data_line only, nothing is written to disk.
+    /// Used directly for previews, and by apply() for real edits.
+    pub fn apply_edit(&mut self, edit: &LineEdit) -> io::Result<PatchOutcome> {
+        let to_lines = |lines: &[String]| -> Vec<Line> {
+            lines
+                .iter()
+                .map(|text| Line {
+                    number: 0,
+                    data: text.clone(),
+                })
+                .collect()
+        };
+
+        let line = match edit {
+            LineEdit::Insert { line, lines } => {
+                Self::check_line(*line)?;
+                // Add empty line if we not have plase for add number line
+                self.pad_to(line - 1);
+                self.data_line.splice(line - 1..line - 1, to_lines(lines));
+                *line
+            }
+            LineEdit::Edit { line, lines } => {
+                Self::check_line(*line)?;
+                // Если файл пустой или строка больше текущего размера - добавляем недостающие строки
+                self.pad_to(*line);
+                self.data_line.splice(line - 1..*line, to_lines(lines));
+                *line
+            }
+            LineEdit::Replace { start, end, lines } => {
+                self.check_range(*start, *end)?;
+                self.data_line.splice(start - 1..*end, to_lines(lines));
+                *start
+            }
+            LineEdit::Delete { start, end } => {
+                self.check_range(*start, *end)?;
+                self.data_line.drain(start - 1..*end);
+                *start
+            }
+            LineEdit::Set(lines) => {
+                self.data_line = to_lines(lines);
+                1
+            }
+            LineEdit::Patch { find, replace } => {
+                let content = self
+                    .data_line
+                    .iter()
+                    .map(|l| l.data.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                let matches: Vec<usize> = content.match_indices(find.as_str()).map(|(i, _)| i).collect();
+                let start = match matches.as_slice() {
+                    [] => return Ok(PatchOutcome::NotFound),
+                    [start] => *start,
+                    many => return Ok(PatchOutcome::Ambiguous(many.len())),
+                };
+
+                let patched = format!("{}{}{}", &content[..start], replace, &content[start + find.len()..]);
+                self.data_line = patched
+                    .split('\n')
+                    .map(|text| Line {
+                        number: 0,
+                        data: text.to_string(),
+                    })
+                    .collect();
+                content[..start].matches('\n').count() + 1
+            }
+        };
+
+        // Numbering all Line in file for context 
+        self.renumber_lines();
+        Ok(PatchOutcome::Applied { line })
+    }
+
+    /// Apply a change and save the file once, nothing is written when a PATCH does not match
+    pub fn apply(&mut self, edit: &LineEdit) -> io::Result<PatchOutcome> {
+        let outcome = self.apply_edit(edit)?;
+        if let PatchOutcome::Applied { .. } = outcome {
+            self.save()?;
+            self.reload()?;
+        }
+        Ok(outcome)
+    }
+
     /// Add new line which shift next line bottom 
+    #[allow(dead_code)]
     pub fn insert_line(&mut self, line_number: usize, new_text: &str) -> io::Result<()> {
+        self.insert_lines(line_number, &[new_text.to_string()])
+    }
+
+    /// Add a block of lines starting at line_number, next lines shift bottom, file is saved once
+    #[allow(dead_code)]
+    pub fn insert_lines(&mut self, line_number: usize, new_lines: &[String]) -> io::Result<()> {
+        self.apply(&LineEdit::Insert { line: line_number, lines: new_lines.to_vec() })?;
+        Ok(())
+    }
+
+    /// Replace line line_number with a block of lines, missing lines are created, file is saved once
+    #[allow(dead_code)]
+    pub fn edit_lines(&mut self, line_number: usize, new_lines: &[String]) -> io::Result<()> {
+        self.apply(&LineEdit::Edit { line: line_number, lines: new_lines.to_vec() })?;
+        Ok(())
+    }
+
+    /// Delete string for line number 
+    #[allow(dead_code)]
+    pub fn delete_line(&mut self, line_number: usize) -> io::Result<()> {
+        self.delete_lines(line_number, line_number)
+    }
+
+    /// Replace lines start..=end with a block of lines, the range must exist, file is saved once
+    #[allow(dead_code)]
+    pub fn replace_lines(&mut self, start: usize, end: usize, new_lines: &[String]) -> io::Result<()> {
+        self.apply(&LineEdit::Replace { start, end, lines: new_lines.to_vec() })?;
+        Ok(())
+    }
+
+    /// Delete lines start..=end, the range must exist, file is saved once
+    #[allow(dead_code)]
+    pub fn delete_lines(&mut self, start: usize, end: usize) -> io::Result<()> {
+        self.apply(&LineEdit::Delete { start, end })?;
+        Ok(())
+    }
+
+    /// Replace the whole content, file is saved once
+    #[allow(dead_code)]
+    pub fn set_lines(&mut self, new_lines: &[String]) -> io::Result<()> {
+        self.apply(&LineEdit::Set(new_lines.to_vec()))?;
+        Ok(())
+    }
+
+    /// Replace the exact text `find` with `replace`, only if it occurs exactly once.
+    /// The text may span several lines and start or end in the middle of a line.
+    #[allow(dead_code)]
+    pub fn patch(&mut self, find: &str, replace: &str) -> io::Result<PatchOutcome> {
+        self.apply(&LineEdit::Patch { find: find.to_string(), replace: replace.to_string() })
+    }
+
+    /// File which exists only in memory, save() still writes it to `path`
+    pub fn from_lines<P: AsRef<Path>>(path: P, lines: &[String]) -> Self {
+        let path_ref = path.as_ref();
+        let mut file = File {
+            name: path_ref
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            path: path_ref.to_path_buf(),
+            data_line: Vec::new(),
+            parent_dir: None,
+            size: 0,
+        };
+        file.data_line = lines
+            .iter()
+            .enumerate()
+            .map(|(i, data)| Line { number: i + 1, data: data.clone() })
+            .collect();
+        file
+    }
+
+    /// Current content as plain strings
+    pub fn lines(&self) -> Vec<String> {
+        self.data_line.iter().map(|l| l.data.clone()).collect()
+    }
+
+    fn check_line(line_number: usize) -> io::Result<()> {
         if line_number == 0 {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "Line numbers start from 1",
             ));
         }
+        Ok(())
+    }
 
-        let new_line = Line {
-            number: line_number,
-            data: new_text.to_string(),
-        };
-
-        // Add empty line if we not have plase for add number line
-        while self.data_line.len() < line_number - 1 {
+    fn pad_to(&mut self, len: usize) {
+        while self.data_line.len() < len {
             self.data_line.push(Line {
                 number: self.data_line.len() + 1,
                 data: String::new(),
             });
         }
-
-        // Insert new line which shift 
-        if line_number > self.data_line.len() {
-            self.data_line.push(new_line);
-        } else {
-            self.data_line.insert(line_number - 1, new_line);
-        }
-
-        // Numbering all Line in file for context 
-        self.renumber_lines();
-        self.save()?;
-        self.reload()?;
-        Ok(())
     }
 
-    /// Delete string for line number 
-    pub fn delete_line(&mut self, line_number: usize) -> io::Result<()> {
-        if line_number == 0 {
+    /// Validate range before touching data_line so a bad range never leaves a half applied change
+    fn check_range(&self, start: usize, end: usize) -> io::Result<()> {
+        Self::check_line(start)?;
+
+        if start > end {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                "Line numbers start from 1",
+                format!("Invalid range {}-{}", start, end),
             ));
         }
 
-        if line_number > self.data_line.len() {
+        if end > self.data_line.len() {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
-                format!("Line {} does not exist", line_number),
+                format!("Line {} does not exist (file has {} lines)", end, self.data_line.len()),
             ));
         }
 
-        self.data_line.remove(line_number - 1);
-        
-        // Numbering Line
-        self.renumber_lines();
-        self.save()?;
-        self.reload()?;
         Ok(())
     }
 
@@ -407,4 +669,102 @@ impl File {
             line.number = i + 1;
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(file: &File) -> Vec<&str> {
+        file.data_line.iter().map(|l| l.data.as_str()).collect()
+    }
+
+    #[test]
+    fn line_ranges_are_replaced_and_deleted() {
+        let root = std::env::temp_dir().join(format!("orchestra-lines-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.py"), "1\n2\n3\n4\n5\n").unwrap();
+        let mut file = File::read_from_path_with_parent(root.join("a.py"), &root).unwrap();
+
+        file.replace_lines(2, 3, &["two", "three", "extra"].map(String::from)).unwrap();
+        assert_eq!(lines(&file), ["1", "two", "three", "extra", "4", "5"]);
+        file.delete_lines(4, 6).unwrap();
+        assert_eq!(lines(&file), ["1", "two", "three"]);
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "1\ntwo\nthree\n");
+        assert_eq!(file.data_line[2].number, 3);
+
+        // Bad ranges are rejected before anything is written
+        assert!(file.replace_lines(0, 1, &["x".to_string()]).is_err());
+        assert!(file.delete_lines(3, 2).is_err());
+        assert!(file.delete_lines(2, 4).is_err());
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "1\ntwo\nthree\n");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn patch_needs_exactly_one_match() {
+        let root = std::env::temp_dir().join(format!("orchestra-patch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.py"), "x = 1\ny = 1\nprint(x)\n").unwrap();
+        let mut file = File::read_from_path_with_parent(root.join("a.py"), &root).unwrap();
+
+        assert_eq!(file.patch("z = 1", "z = 2").unwrap(), PatchOutcome::NotFound);
+        assert_eq!(file.patch(" = 1", " = 2").unwrap(), PatchOutcome::Ambiguous(2));
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 1\ny = 1\nprint(x)\n");
+
+        // The match may span lines and end mid-line
+        assert_eq!(file.patch("1\ny = 1\nprint", "2\nprint").unwrap(), PatchOutcome::Applied { line: 1 });
+        assert_eq!(lines(&file), ["x = 2", "print(x)"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Temp dir with an empty `project` and an `outside` dir next to it
+    fn sandbox(test: &str) -> (PathBuf, Dir) {
+        let root = std::env::temp_dir().join(format!("orchestra-resolve-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project/src")).unwrap();
+        fs::create_dir_all(root.join("outside")).unwrap();
+        let dir = Dir::read_from_path(root.join("project")).unwrap();
+        (root, dir)
+    }
+
+    #[test]
+    fn parent_dirs_stay_inside_the_root() {
+        let (root, dir) = sandbox("parent");
+        assert_eq!(dir.resolve("src/../a.py").unwrap(), PathBuf::from("a.py"));
+        assert_eq!(dir.resolve("./src/new/b.py").unwrap(), PathBuf::from("src/new/b.py"));
+        assert_eq!(dir.resolve(".").unwrap(), PathBuf::new());
+        assert!(dir.resolve("../outside/x.py").is_err());
+        assert!(dir.resolve("src/../../outside").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn absolute_paths_must_point_into_the_root() {
+        let (root, dir) = sandbox("absolute");
+        let inside = fs::canonicalize(root.join("project")).unwrap().join("src/a.py");
+        assert_eq!(dir.resolve(&inside.display().to_string()).unwrap(), PathBuf::from("src/a.py"));
+        assert!(dir.resolve("/etc/passwd").is_err());
+        assert!(dir.resolve(&root.join("outside/x.py").display().to_string()).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_root_are_rejected() {
+        let (root, dir) = sandbox("symlink");
+        std::os::unix::fs::symlink(root.join("outside"), root.join("project/link")).unwrap();
+        std::os::unix::fs::symlink(root.join("missing"), root.join("project/dangling")).unwrap();
+        std::os::unix::fs::symlink(root.join("project/src"), root.join("project/inner")).unwrap();
+
+        // The file does not exist yet, its parent already leads outside
+        let err = dir.resolve("link/new.py").unwrap_err();
+        assert!(err.reason.contains("outside of project root"), "{}", err);
+        assert!(dir.resolve("link").is_err());
+        assert_eq!(dir.resolve("dangling/x.py").unwrap_err().reason, "dangling symlink");
+        assert_eq!(dir.resolve("inner/a.py").unwrap(), PathBuf::from("src/a.py"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use super::{Dir, File};
+
+/// Copy-on-write view of a project: reads fall through to disk, writes stay in memory.
+/// Paths are relative to the project root, already checked by Dir::resolve.
+#[derive(Debug, Clone, Default)]
+pub struct Overlay {
+    /// Current content of every touched file, None = deleted
+    files: BTreeMap<String, Option<Vec<String>>>,
+    /// Content on disk before the first change, None = did not exist
+    originals: BTreeMap<String, Option<Vec<String>>>,
+    dirs: BTreeSet<String>,
+}
+
+/// One file which differs from disk
+#[derive(Debug, Clone)]
+pub struct OverlayChange {
+    pub path: String,
+    /// None = file does not exist on disk
+    pub old: Option<Vec<String>>,
+    /// None = file is deleted
+    pub new: Option<Vec<String>>,
+}
+
+fn read_disk(dir: &Dir, path: &str) -> io::Result<Vec<String>> {
+    File::read_from_path_with_parent(dir.path.join(path), &dir.path).map(|f| f.lines())
+}
+
+impl Overlay {
+    pub fn read(&self, dir: &Dir, path: &str) -> io::Result<Vec<String>> {
+        match self.files.get(path) {
+            Some(Some(lines)) => Ok(lines.clone()),
+            Some(None) => Err(io::Error::new(io::ErrorKind::NotFound, format!("{} was deleted", path))),
+            None => read_disk(dir, path),
+        }
+    }
+
+    pub fn exists(&self, dir: &Dir, path: &str) -> bool {
+        match self.files.get(path) {
+            Some(state) => state.is_some(),
+            None => self.dirs.contains(path) || dir.path.join(path).exists(),
+        }
+    }
+
+    pub fn write(&mut self, dir: &Dir, path: &str, lines: Vec<String>) {
+        self.remember(dir, path);
+        self.files.insert(path.to_string(), Some(lines));
+    }
+
+    pub fn remove(&mut self, dir: &Dir, path: &str) -> io::Result<()> {
+        if !self.exists(dir, path) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path)));
+        }
+        self.remember(dir, path);
+        self.files.insert(path.to_string(), None);
+        Ok(())
+    }
+
+    pub fn create_dir(&mut self, path: &str) {
+        self.dirs.insert(path.to_string());
+    }
+
+    /// Directories created only in memory
+    pub fn created_dirs(&self) -> Vec<String> {
+        self.dirs.iter().cloned().collect()
+    }
+
+    /// Files whose content differs from disk, sorted by path
+    pub fn changes(&self) -> Vec<OverlayChange> {
+        self.files
+            .iter()
+            .filter_map(|(path, new)| {
+                let old = self.originals.get(path).cloned().flatten();
+                (old != *new).then(|| OverlayChange { path: path.clone(), old, new: new.clone() })
+            })
+            .collect()
+    }
+
+    fn remember(&mut self, dir: &Dir, path: &str) {
+        if !self.originals.contains_key(path) {
+            let original = read_disk(dir, path).ok();
+            self.originals.insert(path.to_string(), original);
+        }
+    }
+}
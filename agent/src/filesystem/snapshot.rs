@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::Dir;
+
+/// Files as they were before a script touched them, used to roll the script back.
+/// Paths are relative to the project root.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Original bytes, None = the file did not exist
+    files: BTreeMap<String, Option<Vec<u8>>>,
+    /// Directories which did not exist, removed again on restore
+    dirs: Vec<String>,
+}
+
+impl Snapshot {
+    /// Remember a file before its first change, later calls keep the first version
+    pub fn record(&mut self, dir: &Dir, path: &str) -> io::Result<()> {
+        if self.files.contains_key(path) {
+            return Ok(());
+        }
+        if let Some(parent) = Path::new(path).parent() {
+            self.record_dir(dir, &parent.to_string_lossy());
+        }
+
+        let original = match fs::read(dir.path.join(path)) {
+            Ok(bytes) => Some(bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.files.insert(path.to_string(), original);
+        Ok(())
+    }
+
+    /// Remember every missing directory on the way to `path`
+    pub fn record_dir(&mut self, dir: &Dir, path: &str) {
+        let mut current = Path::new(path);
+        while !current.as_os_str().is_empty() && current != Path::new(".") {
+            let relative = current.to_string_lossy().to_string();
+            if !dir.path.join(current).exists() && !self.dirs.contains(&relative) {
+                self.dirs.push(relative);
+            }
+            current = current.parent().unwrap_or(Path::new(""));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.dirs.is_empty()
+    }
+
+    /// Touched files and created directories
+    pub fn paths(&self) -> Vec<String> {
+        self.files.keys().chain(self.dirs.iter()).cloned().collect()
+    }
+
+    /// Recorded files with their original bytes, None for files which did not exist
+    pub fn files(&self) -> impl Iterator<Item = (&str, Option<&[u8]>)> {
+        self.files.iter().map(|(path, original)| (path.as_str(), original.as_deref()))
+    }
+
+    /// Add the files of a later snapshot, versions already recorded here are older and win
+    pub fn merge(&mut self, later: Snapshot) {
+        for (path, original) in later.files {
+            self.files.entry(path).or_insert(original);
+        }
+        for created in later.dirs {
+            if !self.dirs.contains(&created) {
+                self.dirs.push(created);
+            }
+        }
+    }
+
+    /// Put every recorded file back, delete created files and directories, then reload the tree
+    pub fn restore(&self, dir: &mut Dir) -> io::Result<()> {
+        for (path, original) in &self.files {
+            let full_path = dir.path.join(path);
+            match original {
+                Some(bytes) => {
+                    if let Some(parent) = full_path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&full_path, bytes)?;
+                }
+                None => match fs::remove_file(&full_path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                },
+            }
+        }
+
+        // Deepest first, a directory which got other content stays
+        let mut dirs = self.dirs.clone();
+        dirs.sort_by_key(|d| std::cmp::Reverse(Path::new(d).components().count()));
+        for created in dirs {
+            let _ = fs::remove_dir(dir.path.join(created));
+        }
+
+        dir.reload()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coderun::parser::{parse_and_execute, ExecOptions, CMD};
+    use crate::coderun::policy::OnError;
+    use super::*;
+
+    fn project(test: &str) -> (std::path::PathBuf, Dir) {
+        let root = std::env::temp_dir().join(format!("orchestra-snapshot-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.py"), "x = 1\n").unwrap();
+        fs::write(root.join("gone.py"), "bye\n").unwrap();
+        let dir = Dir::read_from_path(&root).unwrap();
+        (root, dir)
+    }
+
+    #[test]
+    fn restore_brings_back_edited_created_and_deleted_files() {
+        let (root, mut dir) = project("restore");
+        let mut snapshot = Snapshot::default();
+        for path in ["a.py", "gone.py", "pkg/sub/new.py", "a.py"] {
+            snapshot.record(&dir, path).unwrap();
+        }
+        assert_eq!(snapshot.paths(), ["a.py", "gone.py", "pkg/sub/new.py", "pkg/sub", "pkg"]);
+
+        fs::write(root.join("a.py"), "x = 2\n").unwrap();
+        fs::remove_file(root.join("gone.py")).unwrap();
+        fs::create_dir_all(root.join("pkg/sub")).unwrap();
+        fs::write(root.join("pkg/sub/new.py"), "new\n").unwrap();
+
+        snapshot.restore(&mut dir).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 1\n");
+        assert_eq!(fs::read_to_string(root.join("gone.py")).unwrap(), "bye\n");
+        assert!(!root.join("pkg").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn journal_undoes_every_successful_script() {
+        let (root, mut dir) = project("journal");
+        let mut options = ExecOptions::default();
+        parse_and_execute(&mut dir, r#"EDIT FILE "a.py" LINE 1 PUT "x = 2""#, &mut options).unwrap();
+        parse_and_execute(&mut dir, "EDIT FILE \"a.py\" LINE 1 PUT \"x = 3\"\nCREATE FILE \"b.py\" WITH \"b\"", &mut options).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 3\n");
+
+        // The first script's version of a.py is the one kept
+        options.journal.restore(&mut dir).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 1\n");
+        assert!(!root.join("b.py").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// Creates c.py, edits a.py, deletes gone.py, then fails
+    const FAILING: &str = "CREATE FILE \"c.py\" WITH \"c\"
+EDIT FILE \"a.py\" LINE 1 PUT \"x = 2\"
+APPLY DIFF <<<EOF
+--- a/gone.py
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+EOF
+EDIT FILE \"missing.py\" LINE 1 PUT \"x\"";
+
+    #[test]
+    fn failed_script_is_rolled_back_by_default() {
+        let (root, mut dir) = project("rollback");
+        let mut options = ExecOptions::default();
+        let status = parse_and_execute(&mut dir, FAILING, &mut options).unwrap();
+        assert!(matches!(status.running.last(), Some(CMD::RolledBack { paths }) if paths == &["a.py", "c.py", "gone.py"]));
+        assert!(!root.join("c.py").exists());
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 1\n");
+        assert_eq!(fs::read_to_string(root.join("gone.py")).unwrap(), "bye\n");
+        assert!(options.journal.is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn continued_script_keeps_its_changes_for_undo() {
+        let (root, mut dir) = project("continue");
+        let mut options = ExecOptions { on_error: OnError::Continue, ..ExecOptions::default() };
+        let status = parse_and_execute(&mut dir, FAILING, &mut options).unwrap();
+        assert!(matches!(status.running.last(), Some(CMD::Error { .. })));
+        assert!(root.join("c.py").exists());
+        assert!(!root.join("gone.py").exists());
+
+        options.journal.restore(&mut dir).unwrap();
+        assert!(!root.join("c.py").exists());
+        assert_eq!(fs::read_to_string(root.join("a.py")).unwrap(), "x = 1\n");
+        assert_eq!(fs::read_to_string(root.join("gone.py")).unwrap(), "bye\n");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
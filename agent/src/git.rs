@@ -0,0 +1,454 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+/// Every checkpoint branch lives under `orchestra/<agent>/<timestamp>`
+pub const BRANCH_PREFIX: &str = "orchestra";
+
+/// Worktrees of agents live in the git dir, so the project tree never sees them
+const WORKTREES: &str = "orchestra-worktrees";
+
+/// Run git in `dir`, stdout on success, stderr as the error otherwise
+fn git(dir: &Path, args: &[&str], envs: &[(&str, &str)], input: Option<&str>) -> io::Result<String> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let (Some(text), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(text.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(format!("git {}: {}", args.join(" "), stderr)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+/// Branch which gets one commit per script of an agent task.
+/// Commits are written with plumbing and a private index, the user's branch, index and working tree are not touched.
+pub struct Checkpoints {
+    /// Project dir, somewhere inside the work tree
+    dir: PathBuf,
+    pub branch: String,
+    agent: String,
+    /// Last commit of the branch, None in a repository without commits
+    head: Option<String>,
+    index: PathBuf,
+}
+
+impl Checkpoints {
+    /// Start a branch for an agent task, None when the project is not inside a git repository.
+    /// Uncommitted changes of the user go into a baseline commit, so checkpoints contain only agent changes.
+    pub fn start(dir: &Path, agent: &str) -> io::Result<Option<Self>> {
+        if git(dir, &["rev-parse", "--is-inside-work-tree"], &[], None).is_err() {
+            return Ok(None);
+        }
+        let git_dir = git(dir, &["rev-parse", "--absolute-git-dir"], &[], None)?;
+        let (name, stamp) = branch_parts(dir, agent);
+
+        let mut checkpoints = Checkpoints {
+            dir: dir.to_path_buf(),
+            branch: format!("{}/{}/{}", BRANCH_PREFIX, name, stamp),
+            agent: agent.to_string(),
+            head: git(dir, &["rev-parse", "--verify", "-q", "HEAD"], &[], None).ok(),
+            index: PathBuf::from(git_dir).join(format!("orchestra-index-{}-{}", name, stamp)),
+        };
+
+        let tree = checkpoints.write_tree()?;
+        if checkpoints.head_tree()?.as_deref() != Some(tree.as_str()) {
+            checkpoints.commit_tree(&tree, &format!("Baseline before {} task", agent))?;
+        } else if let Some(head) = checkpoints.head.clone() {
+            checkpoints.update_ref(&head)?;
+        }
+        Ok(Some(checkpoints))
+    }
+
+    /// Commit the project as it is now, None when nothing changed since the last checkpoint
+    pub fn commit(&mut self, message: &str) -> io::Result<Option<String>> {
+        let tree = self.write_tree()?;
+        if self.head_tree()?.as_deref() == Some(tree.as_str()) {
+            return Ok(None);
+        }
+        self.commit_tree(&tree, message).map(Some)
+    }
+
+    fn head_tree(&self) -> io::Result<Option<String>> {
+        match &self.head {
+            Some(head) => git(&self.dir, &["rev-parse", &format!("{}^{{tree}}", head)], &[], None).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Tree of the last checkpoint with the project dir replaced by its current content
+    fn write_tree(&self) -> io::Result<String> {
+        let index = self.index.to_string_lossy().to_string();
+        let env = [("GIT_INDEX_FILE", index.as_str())];
+
+        match &self.head {
+            Some(head) => git(&self.dir, &["read-tree", head], &env, None)?,
+            None => git(&self.dir, &["read-tree", "--empty"], &env, None)?,
+        };
+        git(&self.dir, &["add", "-A", "--", "."], &env, None)?;
+        git(&self.dir, &["write-tree"], &env, None)
+    }
+
+    fn commit_tree(&mut self, tree: &str, message: &str) -> io::Result<String> {
+        let identity = identity(&self.dir, &self.agent);
+        let env: Vec<(&str, &str)> = identity.iter().map(|(k, v)| (*k, v.as_str())).collect();
+
+        let mut args = vec!["commit-tree", tree, "-F", "-"];
+        if let Some(head) = &self.head {
+            args.extend(["-p", head.as_str()]);
+        }
+        let commit = git(&self.dir, &args, &env, Some(message))?;
+
+        self.update_ref(&commit)?;
+        self.head = Some(commit.clone());
+        Ok(commit)
+    }
+
+    fn update_ref(&self, commit: &str) -> io::Result<()> {
+        git(&self.dir, &["update-ref", &format!("refs/heads/{}", self.branch), commit], &[], None)?;
+        Ok(())
+    }
+}
+
+impl Drop for Checkpoints {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.index);
+    }
+}
+
+/// Branch name of an agent and the time, `Alex Smith` → (`Alex-Smith`, `20250101-120000`)
+fn branch_parts(dir: &Path, agent: &str) -> (String, String) {
+    let name: String = agent
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+
+    // Tasks of the same agent may start within the same second
+    let now = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut stamp = now.clone();
+    let mut n = 1;
+    let taken = |stamp: &str| {
+        let branch = format!("refs/heads/{}/{}/{}", BRANCH_PREFIX, name, stamp);
+        git(dir, &["rev-parse", "--verify", "-q", &branch], &[], None).is_ok()
+    };
+    while taken(&stamp) {
+        n += 1;
+        stamp = format!("{}-{}", now, n);
+    }
+    (name, stamp)
+}
+
+/// Author of agent commits. Without a configured identity the agent commits as itself
+fn identity(dir: &Path, agent: &str) -> Vec<(&'static str, String)> {
+    let email = format!("{}@orchestra", agent.to_lowercase().replace(' ', "-"));
+    let mut env = vec![("GIT_AUTHOR_NAME", agent.to_string()), ("GIT_AUTHOR_EMAIL", email.clone())];
+    if git(dir, &["config", "user.email"], &[], None).is_err() {
+        env.push(("GIT_COMMITTER_NAME", agent.to_string()));
+        env.push(("GIT_COMMITTER_EMAIL", email));
+    }
+    env
+}
+
+/// Own checkout of the project for one agent, on a new branch from HEAD.
+/// The agent works there undisturbed, `merge` brings its branch back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Worktree {
+    pub agent: String,
+    pub branch: String,
+    /// Top of the checkout
+    pub root: PathBuf,
+    /// Project dir inside the checkout
+    pub dir: PathBuf,
+}
+
+/// How the merge of one worktree ended
+pub enum Merge {
+    /// Merged into the current branch, the worktree and its branch are removed
+    Merged,
+    /// Merge aborted, the worktree is kept for the operator
+    Conflicts(Vec<String>),
+}
+
+impl Worktree {
+    /// Check out HEAD for an agent, None when the project is not inside a git repository
+    pub fn create(dir: &Path, agent: &str) -> io::Result<Option<Self>> {
+        if git(dir, &["rev-parse", "--is-inside-work-tree"], &[], None).is_err() {
+            return Ok(None);
+        }
+        let head = git(dir, &["rev-parse", "--verify", "-q", "HEAD"], &[], None)
+            .map_err(|_| io::Error::other("worktrees start from HEAD, commit something first"))?;
+        let common = git(dir, &["rev-parse", "--path-format=absolute", "--git-common-dir"], &[], None)?;
+        let prefix = git(dir, &["rev-parse", "--show-prefix"], &[], None)?;
+
+        let (name, stamp) = branch_parts(dir, agent);
+        let branch = format!("{}/{}/{}", BRANCH_PREFIX, name, stamp);
+        let root = Path::new(&common).join(WORKTREES).join(format!("{}-{}", name, stamp));
+        git(dir, &["worktree", "add", "-q", "-b", &branch, &root.to_string_lossy(), &head], &[], None)?;
+
+        Ok(Some(Worktree { agent: agent.to_string(), branch, dir: root.join(prefix), root }))
+    }
+
+    /// Commit everything changed in the checkout, None when nothing changed
+    pub fn commit(&self, message: &str) -> io::Result<Option<String>> {
+        git(&self.root, &["add", "-A"], &[], None)?;
+        if git(&self.root, &["diff", "--cached", "--quiet"], &[], None).is_ok() {
+            return Ok(None);
+        }
+        let identity = identity(&self.root, &self.agent);
+        let env: Vec<(&str, &str)> = identity.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        git(&self.root, &["commit", "-q", "-F", "-"], &env, Some(message))?;
+        git(&self.root, &["rev-parse", "--short", "HEAD"], &[], None).map(Some)
+    }
+
+    /// Merge the branch into the current branch of the project's work tree.
+    /// On conflicts the merge is aborted, so the work tree stays as it was.
+    pub fn merge(&self, dir: &Path) -> io::Result<Merge> {
+        self.commit(&format!("Unfinished work of {}", self.agent))?;
+
+        let top = git(dir, &["rev-parse", "--show-toplevel"], &[], None)?;
+        let top = Path::new(&top);
+        let identity = identity(top, "Orchestra");
+        let env: Vec<(&str, &str)> = identity.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let message = format!("Merge work of {} from {}", self.agent, self.branch);
+
+        if let Err(e) = git(top, &["merge", "--no-ff", "-m", &message, &self.branch], &env, None) {
+            let files = git(top, &["diff", "--name-only", "--diff-filter=U"], &[], None)?;
+            // Refused before it started, e.g. over uncommitted changes
+            if files.is_empty() {
+                return Err(e);
+            }
+            git(top, &["merge", "--abort"], &[], None)?;
+            return Ok(Merge::Conflicts(files.lines().map(str::to_string).collect()));
+        }
+
+        git(top, &["worktree", "remove", "--force", &self.root.to_string_lossy()], &[], None)?;
+        git(top, &["branch", "-D", &self.branch], &[], None)?;
+        Ok(Merge::Merged)
+    }
+}
+
+/// Worktrees of agents which are not merged yet
+pub fn worktrees(dir: &Path) -> io::Result<Vec<Worktree>> {
+    if git(dir, &["rev-parse", "--is-inside-work-tree"], &[], None).is_err() {
+        return Ok(Vec::new());
+    }
+    let prefix = git(dir, &["rev-parse", "--show-prefix"], &[], None)?;
+    let porcelain = git(dir, &["worktree", "list", "--porcelain"], &[], None)?;
+
+    let mut found = Vec::new();
+    for block in porcelain.split("\n\n") {
+        let mut root = None;
+        let mut branch = None;
+        for line in block.lines() {
+            if let Some(path) = line.strip_prefix("worktree ") {
+                root = Some(PathBuf::from(path));
+            } else if let Some(name) = line.strip_prefix("branch refs/heads/") {
+                branch = Some(name.to_string());
+            }
+        }
+        if let (Some(root), Some(branch)) = (root, branch)
+            && root.parent().is_some_and(|p| p.ends_with(WORKTREES))
+            && let Some(agent) = branch.strip_prefix(&format!("{}/", BRANCH_PREFIX)).and_then(|b| b.split('/').next())
+        {
+            found.push(Worktree { agent: agent.to_string(), dir: root.join(&prefix), root, branch });
+        }
+    }
+    Ok(found)
+}
+
+/// Every checkpoint branch with the commits it has on top of HEAD
+pub fn list(dir: &Path) -> io::Result<String> {
+    let refs = git(
+        dir,
+        &["for-each-ref", "--sort=refname", "--format=%(refname:short)", &format!("refs/heads/{}/", BRANCH_PREFIX)],
+        &[],
+        None,
+    )?;
+
+    let mut out = Vec::new();
+    for branch in refs.lines().filter(|l| !l.is_empty()) {
+        out.push(branch.to_string());
+        let log = git(
+            dir,
+            &["log", "--format=  %h %ad %s", "--date=format:%Y-%m-%d %H:%M", branch, "--not", "HEAD"],
+            &[],
+            None,
+        )
+        // A repository without commits has no HEAD
+        .or_else(|_| git(dir, &["log", "--format=  %h %ad %s", "--date=format:%Y-%m-%d %H:%M", branch], &[], None))?;
+        if log.is_empty() {
+            out.push("  (merged into HEAD)".to_string());
+        } else {
+            out.push(log);
+        }
+    }
+    Ok(out.join("\n"))
+}
+
+/// Message and patch of one checkpoint
+pub fn show(dir: &Path, checkpoint: &str) -> io::Result<String> {
+    git(dir, &["show", "--stat", "--patch", "--format=%h %an %ad%n%n%B", "--date=format:%Y-%m-%d %H:%M", checkpoint], &[], None)
+}
+
+/// Undo the changes of one checkpoint in the working tree, nothing is committed
+pub fn revert(dir: &Path, checkpoint: &str) -> io::Result<()> {
+    // --root diffs a checkpoint without parent, e.g. the baseline in a fresh repository, against the empty tree
+    let patch = git(dir, &["diff-tree", "-p", "--binary", "--root", "--no-commit-id", checkpoint], &[], None)?;
+    if patch.is_empty() {
+        return Ok(());
+    }
+    // Diff paths are relative to the top of the work tree
+    let top = git(dir, &["rev-parse", "--show-toplevel"], &[], None)?;
+    git(Path::new(&top), &["apply", "--reverse", "-"], &[], Some(&format!("{}\n", patch)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Repository with one commit holding `project/a.py`
+    fn repo(test: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("orchestra-git-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project")).unwrap();
+        fs::write(root.join("project/a.py"), "x = 1\n").unwrap();
+        for args in [
+            &["init", "-q", "-b", "main"][..],
+            &["config", "user.email", "dev@example.com"],
+            &["config", "user.name", "Dev"],
+            &["add", "-A"],
+            &["commit", "-q", "-m", "init"],
+        ] {
+            git(&root, args, &[], None).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn checkpoints_are_listed_and_reverted() {
+        let root = repo("checkpoints");
+        let project = root.join("project");
+        let head = git(&root, &["rev-parse", "HEAD"], &[], None).unwrap();
+
+        let mut checkpoints = Checkpoints::start(&project, "Alex").unwrap().unwrap();
+        assert!(checkpoints.branch.starts_with("orchestra/Alex/"));
+        assert_eq!(checkpoints.commit("nothing").unwrap(), None);
+
+        fs::write(project.join("a.py"), "x = 2\n").unwrap();
+        fs::write(project.join("b.py"), "b\n").unwrap();
+        let commit = checkpoints.commit("Edit a.py").unwrap().unwrap();
+
+        // The user's branch and index stay where they were
+        assert_eq!(git(&root, &["rev-parse", "HEAD"], &[], None).unwrap(), head);
+        assert_eq!(git(&root, &["diff", "--cached", "--name-only"], &[], None).unwrap(), "");
+
+        let listed = list(&project).unwrap();
+        assert!(listed.starts_with(&checkpoints.branch), "{}", listed);
+        assert!(listed.contains("Edit a.py"), "{}", listed);
+        assert!(show(&project, &commit).unwrap().contains("+x = 2"));
+
+        revert(&project, &commit).unwrap();
+        assert_eq!(fs::read_to_string(project.join("a.py")).unwrap(), "x = 1\n");
+        assert!(!project.join("b.py").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn same_second_tasks_get_their_own_branch() {
+        let root = repo("collision");
+        let project = root.join("project");
+        let first = Checkpoints::start(&project, "Alex").unwrap().unwrap();
+        let second = Checkpoints::start(&project, "Alex").unwrap().unwrap();
+        let third = Checkpoints::start(&project, "Alex").unwrap().unwrap();
+        assert_ne!(first.branch, second.branch);
+        assert_ne!(second.branch, third.branch);
+
+        let worktree = Worktree::create(&project, "Alex").unwrap().unwrap();
+        let other = Worktree::create(&project, "Alex").unwrap().unwrap();
+        assert_ne!(worktree.branch, other.branch);
+        assert_ne!(worktree.root, other.root);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn checkpoint_without_parent_is_reverted() {
+        let root = std::env::temp_dir().join(format!("orchestra-git-root-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project")).unwrap();
+        git(&root, &["init", "-q", "-b", "main"], &[], None).unwrap();
+        let project = root.join("project");
+        fs::write(project.join("a.py"), "x = 1\n").unwrap();
+
+        // Without commits the baseline is the root commit of the branch
+        let checkpoints = Checkpoints::start(&project, "Alex").unwrap().unwrap();
+        let baseline = checkpoints.head.clone().unwrap();
+        revert(&project, &baseline).unwrap();
+        assert!(!project.join("a.py").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn no_repository_means_no_checkpoints() {
+        let root = std::env::temp_dir().join(format!("orchestra-git-none-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        assert!(Checkpoints::start(&root, "Alex").unwrap().is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn worktree_is_merged_back_and_removed() {
+        let root = repo("worktree");
+        let project = root.join("project");
+        let worktree = Worktree::create(&project, "Alex").unwrap().unwrap();
+        assert!(worktree.dir.ends_with("project"));
+        fs::write(worktree.dir.join("a.py"), "x = 2\n").unwrap();
+
+        let listed = worktrees(&project).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].agent.as_str(), listed[0].branch.as_str()), ("Alex", worktree.branch.as_str()));
+
+        assert!(matches!(worktree.merge(&project).unwrap(), Merge::Merged));
+        assert_eq!(fs::read_to_string(project.join("a.py")).unwrap(), "x = 2\n");
+        assert!(worktrees(&project).unwrap().is_empty());
+        assert!(!worktree.root.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn conflicting_merge_is_aborted() {
+        let root = repo("conflict");
+        let project = root.join("project");
+        let worktree = Worktree::create(&project, "Alex").unwrap().unwrap();
+        fs::write(worktree.dir.join("a.py"), "x = 2\n").unwrap();
+        fs::write(project.join("a.py"), "x = 3\n").unwrap();
+        git(&root, &["commit", "-q", "-am", "user change"], &[], None).unwrap();
+
+        match worktree.merge(&project).unwrap() {
+            Merge::Conflicts(files) => assert_eq!(files, ["project/a.py"]),
+            Merge::Merged => panic!("merge should conflict"),
+        }
+        // Work tree as before the merge, the agent's work is kept
+        assert_eq!(fs::read_to_string(project.join("a.py")).unwrap(), "x = 3\n");
+        assert_eq!(git(&root, &["status", "--porcelain"], &[], None).unwrap(), "");
+        assert_eq!(fs::read_to_string(worktree.dir.join("a.py")).unwrap(), "x = 2\n");
+        assert_eq!(worktrees(&project).unwrap().len(), 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
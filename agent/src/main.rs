@@ -1,17 +1,39 @@
-use std::{fs, io};
+use std::io;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 mod filesystem;
 mod config;
 mod model;
 mod coderun;
 mod agent;
+mod provider;
+mod git;
+mod error;
+mod context;
+mod session;
+mod manager;
+mod board;
+mod bus;
+mod report;
 
-use toml;
-use filesystem::Dir;
-use config::Config;
+use filesystem::{Dir, FileLocks, Snapshot};
+use config::{Config, Employee, Manager};
 use model::Promt;
 use agent::Agent;
+use coderun::parser::ExecOptions;
+use coderun::parser::CMD;
+use coderun::workspace::{journal_diff, DryRun};
+use manager::{ManagerAgent, Member, Subtask};
+use coderun::approval::{Operator, Review};
+use error::OrchestraError;
+use context::ContextBudget;
+use session::{Session, SessionState};
+use git::{Merge, Worktree};
+use provider::ModelProvider;
+use board::StatusBoard;
+use bus::MessageBus;
 
 // Color ANSI
 const RED: &str = "\x1b[31m";
@@ -23,7 +45,35 @@ const BLUE: &str = "\x1b[34m";
 const PURPLE: &str = "\x1b[35m";
 const RESET: &str = "\x1b[0m";
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Settings and history of the interactive shell
+#[derive(Default)]
+struct Shell {
+    approval: bool,
+    dry_run: bool,
+    /// Original files of every finished task, newest last
+    undo: Vec<Snapshot>,
+    /// ASK and NOTIFY between agents, notes wait in the inboxes until their agent runs
+    bus: MessageBus,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}{}{}", RED, e, RESET);
+        std::process::exit(1);
+    }
+}
+
+/// Prompt line without the newline, None when stdin is closed
+fn read_line() -> Option<String> {
+    io::stdout().flush().ok();
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim().to_string()),
+    }
+}
+
+fn run() -> Result<(), OrchestraError> {
     let letters = [
         (RED, "O"),
         (ORANGE, "R"),
@@ -41,30 +91,34 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
     println!(" {}*****{}", BLUE, RESET);
     println!("{}Hi in Orchestra{} \n Dev: claus0nori@gmail.com", GREEN, RESET);
-    println!("{}task{} - create new task", YELLOW, RESET);
+    println!("{}task{} - create new task for an employee, or a manager who delegates it", YELLOW, RESET);
     println!("{}help{} - show help menu", YELLOW, RESET);
     println!("{}ls{} - show file in dir projects", YELLOW, RESET);
+    println!("{}approval{} - review agent changes before they apply (on/off)", YELLOW, RESET);
+    println!("{}dryrun{} - simulate tasks without touching the project (on/off)", YELLOW, RESET);
+    println!("{}undo{} - restore files changed by the last task", YELLOW, RESET);
+    println!("{}resume <id>{} - continue a stored session, without id lists them", YELLOW, RESET);
+    println!("{}parallel{} - run several employees at once, each on its own task", YELLOW, RESET);
+    println!("{}checkpoints{} - list git checkpoints of agents, {}diff{} / {}revert{} one of them", YELLOW, RESET, YELLOW, RESET, YELLOW, RESET);
+    println!("{}merge [branch]{} - merge the worktrees of agents back", YELLOW, RESET);
     println!("{}*in dev* emp{} - show current employment", YELLOW, RESET);
     println!("{}*in dev* manager{} - show Manager", YELLOW, RESET);
     println!("{}exit{} - close program", YELLOW, RESET);
 
-    let content = fs::read_to_string("orc.toml")?;
-    let config: Config = toml::from_str(&content).expect("Error parsing config");
+    let config = Config::load("orc.toml")?;
+    let mut shell = Shell { bus: MessageBus::new(&config), ..Shell::default() };
 
     loop {
         println!("{}*** Load project in memory ***{}", BLUE, RESET);
         let mut dir = Dir::read_from_path_with_options(
             config.project.dir.clone(),
             config.project.ignore_dir.clone(),
-            Some(config.project.max_size.clone())
+            Some(config.project.max_size)
         )?;
 
         print!("{}> {}", GREEN, RESET);
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let trimmed = input.trim();
+        let Some(input) = read_line() else { break };
+        let trimmed = input.as_str();
 
         match trimmed {
             "exit" => break,
@@ -72,19 +126,142 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "task" => { 
                 println!("{}Who will work on this task?{}", YELLOW, RESET);
                 print!("> ");
-                io::stdout().flush().unwrap();
-                let mut agent_name = String::new();
-                io::stdin().read_line(&mut agent_name).unwrap();
-                let agent_name = agent_name.trim().to_string();
+                let Some(agent_name) = read_line() else { break };
+                let manager = config.manager(&agent_name);
+                if manager.is_none()
+                    && let Err(e) = config.employee(&agent_name)
+                {
+                    eprintln!("{}{}{}", RED, e, RESET);
+                    continue;
+                }
 
                 println!("{}Describe the task for {}:{}", YELLOW, agent_name, RESET);
                 print!("> ");
-                io::stdout().flush().unwrap();
-                let mut task_msg = String::new();
-                io::stdin().read_line(&mut task_msg).unwrap();
-                let task_msg = task_msg.trim().to_string();
+                let Some(task_msg) = read_line() else { break };
 
-                load_project(&config, &mut dir, agent_name, task_msg)?;
+                let result = match manager {
+                    Some(manager) => run_manager(&config, &mut dir, manager, task_msg, &mut shell),
+                    None => load_project(&config, &mut dir, agent_name, task_msg, &mut shell),
+                };
+                if let Err(e) = result {
+                    eprintln!("{}Task failed: {}{}", RED, e, RESET);
+                }
+            },
+            "parallel" => {
+                println!("{}One task per line as `Name: task`, finish with an empty line:{}", YELLOW, RESET);
+                let mut jobs: Vec<(String, String)> = Vec::new();
+                loop {
+                    print!("> ");
+                    let Some(line) = read_line() else { break };
+                    if line.is_empty() {
+                        break;
+                    }
+                    let Some((name, task)) = line.split_once(':') else {
+                        eprintln!("{}Expected `Name: task`{}", RED, RESET);
+                        continue;
+                    };
+                    let (name, task) = (name.trim(), task.trim());
+                    if let Err(e) = config.employee(name) {
+                        eprintln!("{}{}{}", RED, e, RESET);
+                    } else if jobs.iter().any(|(n, _)| n == name) {
+                        eprintln!("{}{} already has a task in this run{}", RED, name, RESET);
+                    } else if task.is_empty() {
+                        eprintln!("{}The task for {} is empty{}", RED, name, RESET);
+                    } else {
+                        jobs.push((name.to_string(), task.to_string()));
+                    }
+                }
+                if jobs.is_empty() {
+                    println!("{}Nothing to run{}", YELLOW, RESET);
+                    continue;
+                }
+                if let Err(e) = run_parallel(&config, &mut dir, jobs, &mut shell) {
+                    eprintln!("{}Task failed: {}{}", RED, e, RESET);
+                }
+            },
+            "approval" => {
+                shell.approval = !shell.approval;
+                let state = if shell.approval { "on" } else { "off" };
+                println!("{}Approval mode {}{}", BLUE, state, RESET);
+            },
+            "dryrun" => {
+                shell.dry_run = !shell.dry_run;
+                let state = if shell.dry_run { "on" } else { "off" };
+                println!("{}Dry-run mode {}{}", BLUE, state, RESET);
+            },
+            "checkpoints" => match git::list(&dir.path) {
+                Ok(list) if list.is_empty() => println!("{}No checkpoints yet{}", YELLOW, RESET),
+                Ok(list) => println!("{}", list),
+                Err(e) => eprintln!("{}{}{}", RED, e, RESET),
+            },
+            "diff" | "revert" => {
+                println!("{}Which checkpoint?{}", YELLOW, RESET);
+                print!("> ");
+                let Some(checkpoint) = read_line() else { break };
+                let checkpoint = checkpoint.as_str();
+
+                let result = if trimmed == "diff" {
+                    git::show(&dir.path, checkpoint).map(|patch| println!("{}", patch))
+                } else {
+                    git::revert(&dir.path, checkpoint)
+                        .map(|_| println!("{}Reverted {} in the working tree{}", GREEN, checkpoint, RESET))
+                };
+                if let Err(e) = result {
+                    eprintln!("{}{}{}", RED, e, RESET);
+                }
+            },
+            cmd if cmd.split_whitespace().next() == Some("merge") => {
+                let only = cmd.split_whitespace().nth(1);
+                let worktrees = match git::worktrees(&dir.path) {
+                    Ok(list) => list.into_iter().filter(|w| only.is_none_or(|b| w.branch == b)).collect::<Vec<_>>(),
+                    Err(e) => {
+                        eprintln!("{}{}{}", RED, e, RESET);
+                        continue;
+                    }
+                };
+                if worktrees.is_empty() {
+                    println!("{}No worktrees to merge{}", YELLOW, RESET);
+                }
+                for worktree in worktrees {
+                    match worktree.merge(&dir.path) {
+                        Ok(Merge::Merged) => println!("{}Merged {}{}", GREEN, worktree.branch, RESET),
+                        Ok(Merge::Conflicts(files)) => {
+                            eprintln!("{}Conflicts in {}, merge aborted:{}", RED, worktree.branch, RESET);
+                            for file in files {
+                                eprintln!("  {}", file);
+                            }
+                            eprintln!("{}Resolve them with `git merge {}`, the worktree stays in {}{}", YELLOW, worktree.branch, worktree.root.display(), RESET);
+                        }
+                        Err(e) => eprintln!("{}Merge of {} failed: {}{}", RED, worktree.branch, e, RESET),
+                    }
+                }
+            },
+            cmd if cmd.split_whitespace().next() == Some("resume") => {
+                let id = match cmd.split_whitespace().nth(1) {
+                    Some(id) => id.to_string(),
+                    None => {
+                        let sessions = session::list(&config);
+                        if sessions.is_empty() {
+                            println!("{}No sessions yet{}", YELLOW, RESET);
+                            continue;
+                        }
+                        println!("{}", sessions.join("\n"));
+                        println!("{}Which session?{}", YELLOW, RESET);
+                        print!("> ");
+                        let Some(id) = read_line() else { break };
+                        id
+                    }
+                };
+                if let Err(e) = resume_session(&config, &mut dir, &id, &mut shell) {
+                    eprintln!("{}Task failed: {}{}", RED, e, RESET);
+                }
+            },
+            "undo" => match shell.undo.pop() {
+                Some(snapshot) => match snapshot.restore(&mut dir) {
+                    Ok(()) => println!("{}Restored:{} {}", GREEN, RESET, snapshot.paths().join(", ")),
+                    Err(e) => eprintln!("{}Undo failed: {}{}", RED, e, RESET),
+                },
+                None => println!("{}Nothing to undo{}", YELLOW, RESET),
             },
             "emp" => {
                 println!("{}=== Employees ==={}", BLUE, RESET);
@@ -113,20 +290,473 @@ fn help_menu() {
     println!("{}Commands:{}", BLUE, RESET);
     println!("{}exit{} - quit program", YELLOW, RESET);
     println!("{}help{} - show this menu", YELLOW, RESET);
-    println!("{}task{} - create new task", YELLOW, RESET);
+    println!("{}task{} - create new task, a manager plans it and delegates to the team", YELLOW, RESET);
     println!("{}emp{} - show employees", YELLOW, RESET);
     println!("{}manager{} - show managers", YELLOW, RESET);
     println!("{}ls{} - list project directory", YELLOW, RESET);
+    println!("{}approval{} - toggle review of every change and RUN of agents", YELLOW, RESET);
+    println!("{}dryrun{} - toggle simulation in memory, prints the diff at the end", YELLOW, RESET);
+    println!("{}undo{} - restore the files of the last task, created files are deleted", YELLOW, RESET);
+    println!("{}resume <id>{} - continue a task stored in <employee dir>/sessions", YELLOW, RESET);
+    println!("{}parallel{} - one `Name: task` per line, the agents run at once and never write the same file", YELLOW, RESET);
+    println!("{}checkpoints{} - list orchestra/<agent>/<time> branches with a commit per script", YELLOW, RESET);
+    println!("{}diff{} - show one checkpoint", YELLOW, RESET);
+    println!("{}revert{} - undo one checkpoint in the working tree", YELLOW, RESET);
+    println!("{}merge [branch]{} - merge agent worktrees into the current branch, conflicts are aborted and listed", YELLOW, RESET);
+}
+
+fn load_project(config: &Config, dir: &mut Dir, agent_name: String, task_msg: String, shell: &mut Shell) -> Result<(), OrchestraError> {
+
+    let employee = config.employee(&agent_name)?;
+    let promt = Promt::new(agent_name.clone(), dir.clone(), config.employee.clone(), task_msg.clone())?;
+
+    let mut agent = Agent::new(promt, employee, build_provider(config, employee)?, exec_options(config, employee, shell));
+    // A simulated task leaves nothing on disk which could be resumed
+    if !shell.dry_run {
+        agent.session = Some(Session::new(&employee.dir, &agent_name, &task_msg));
+    }
+    match open_worktree(config, dir, &agent_name, shell)? {
+        Some((worktree, mut own)) => {
+            if let Some(session) = &mut agent.session {
+                session.worktree = Some(worktree.clone());
+            }
+            run_agent(config, &mut own, employee, &mut agent, shell, Some(&worktree))
+        }
+        None => run_agent(config, dir, employee, &mut agent, shell, None),
+    }
 }
 
-fn load_project(config: &Config, dir: &mut Dir, agent_name: String, task_msg: String) -> std::io::Result<()> {
+/// Own git worktree for the agent when `project.worktrees` is on, with the project read from it
+fn open_worktree(config: &Config, dir: &Dir, agent: &str, shell: &Shell) -> Result<Option<(Worktree, Dir)>, OrchestraError> {
+    if !config.project.worktrees || shell.dry_run {
+        return Ok(None);
+    }
+    let Some(worktree) = Worktree::create(&dir.path, agent)? else {
+        eprintln!("{}Worktrees disabled: the project is not in a git repository{}", YELLOW, RESET);
+        return Ok(None);
+    };
+    println!("{}{} works in {} on branch {}{}", BLUE, agent, worktree.root.display(), worktree.branch, RESET);
+    let own = worktree_dir(config, &worktree)?;
+    Ok(Some((worktree, own)))
+}
 
-    let promt = Promt::new(agent_name, dir.clone(), config.employee.clone(), task_msg);
+fn worktree_dir(config: &Config, worktree: &Worktree) -> Result<Dir, OrchestraError> {
+    Ok(Dir::read_from_path_with_options(&worktree.dir, config.project.ignore_dir.clone(), Some(config.project.max_size))?)
+}
 
-    let mut agent = Agent::new(promt);
+/// Commit the work of an agent on its worktree branch, the line to show the operator
+fn commit_worktree(worktree: &Worktree, task: &str) -> String {
+    let message = format!("{}: {}", worktree.agent, task.lines().next().unwrap_or_default());
+    match worktree.commit(&message) {
+        Ok(Some(commit)) => format!("{}Committed {} on {}, type `merge` to bring it in{}", GREEN, commit, worktree.branch, RESET),
+        Ok(None) => format!("{}No changes on {}{}", YELLOW, worktree.branch, RESET),
+        Err(e) => format!("{}Commit on {} failed: {}{}", RED, worktree.branch, e, RESET),
+    }
+}
+
+/// Let a manager plan the task and delegate it to its team
+fn run_manager(config: &Config, dir: &mut Dir, manager: &Manager, task_msg: String, shell: &mut Shell) -> Result<(), OrchestraError> {
+    let subtasks = lead(config, dir, manager, task_msg, shell)?;
+
+    println!("{}=== {}: summary ==={}", BLUE, manager.name, RESET);
+    for subtask in &subtasks {
+        let (color, mark) = if subtask.accepted { (GREEN, "accepted") } else { (YELLOW, "not accepted") };
+        println!(
+            "{}{}{} {} (attempts: {}): {} {}",
+            color, mark, RESET, subtask.employee, subtask.attempts,
+            subtask.task.lines().next().unwrap_or_default(), subtask.note
+        );
+    }
+    Ok(())
+}
+
+/// Let the manager plan the task and run its subtasks with the team, managers of the team lead their own
+fn lead(config: &Config, dir: &mut Dir, manager: &Manager, task_msg: String, shell: &mut Shell) -> Result<Vec<Subtask>, OrchestraError> {
+    let team = manager
+        .team
+        .iter()
+        .map(|name| match config.manager(name) {
+            Some(sub) => Ok(Member::manager(sub)),
+            None => config.employee(name).map(|e| Member::Employee(Box::new(e.clone()))),
+        })
+        .collect::<Result<Vec<Member>, _>>()?;
+    if team.is_empty() {
+        return Err(OrchestraError::Config(format!("manager \"{}\" has no team", manager.name)));
+    }
+
+    let llm = manager.llm.or(&config.llm);
+    let provider = provider::from_config(&llm).map_err(OrchestraError::Provider)?;
+
+    let mut boss = ManagerAgent::new(manager, team, dir, task_msg, provider);
+    boss.budget = ContextBudget::from_config(&llm);
+    boss.bus = Some(Box::new(shell.bus.mailbox(&manager.name)));
+
+    println!("{}{} is planning...{}", BLUE, manager.name, RESET);
+    boss.run(&mut |member, task| match member {
+        Member::Employee(employee) => delegate(config, dir, employee, task, shell),
+        Member::Manager { name, .. } => delegate_to_manager(config, dir, name, task, shell),
+    })
+}
+
+/// Run one subtask of a manager with a lower manager, the report is how its team did
+fn delegate_to_manager(config: &Config, dir: &mut Dir, name: &str, task: &str, shell: &mut Shell) -> Result<Option<String>, OrchestraError> {
+    let manager = config
+        .manager(name)
+        .ok_or_else(|| OrchestraError::Manager(format!("\"{}\" is not a manager", name)))?;
+    let subtasks = lead(config, dir, manager, task.to_string(), shell)?;
+    if subtasks.iter().any(|s| s.aborted) {
+        return Ok(None);
+    }
+    Ok(Some(manager::team_report(&manager.name, &subtasks)))
+}
+
+/// Run one subtask of a manager, the report holds what the agent did and the diff of its changes
+fn delegate(config: &Config, dir: &mut Dir, employee: &Employee, task: &str, shell: &mut Shell) -> Result<Option<String>, OrchestraError> {
+    println!("{}{} is working...{}", BLUE, employee.name, RESET);
+    let promt = Promt::new(employee.name.clone(), dir.clone(), config.employee.clone(), task.to_string())?;
+    let mut agent = Agent::new(promt, employee, build_provider(config, employee)?, exec_options(config, employee, shell));
+    if !shell.dry_run {
+        agent.session = Some(Session::new(&employee.dir, &employee.name, task));
+    }
+
+    // Subtasks run one after another and build on each other, they share the project dir
+    let undo = shell.undo.len();
+    run_agent(config, dir, employee, &mut agent, shell, None)?;
+
+    let aborted = agent
+        .executed
+        .last()
+        .is_some_and(|status| matches!(status.running.last(), Some(CMD::Aborted { .. })));
+    if aborted {
+        return Ok(None);
+    }
+
+    let changes = match (agent.dry_run(), shell.undo.get(undo)) {
+        (Some(dry), _) => dry.report(),
+        (None, Some(journal)) => journal_diff(journal, dir),
+        (None, None) => "no file changes".to_string(),
+    };
+    Ok(Some(format!("{}\n\n=== Changes ===\n{}", agent.report(), changes)))
+}
+
+/// What one agent of a parallel run left behind
+struct Finished {
+    name: String,
+    journal: Snapshot,
+    /// What happened to the worktree branch, None without worktree
+    worktree: Option<String>,
+    session: Option<String>,
+    dry_report: Option<String>,
+    report: Option<std::path::PathBuf>,
+    result: Result<(), String>,
+}
+
+impl Finished {
+    /// Entry for an agent whose thread panicked, its journal went down with it
+    fn crashed(name: &str, agent: &Agent) -> Self {
+        Finished {
+            name: name.to_string(),
+            journal: Snapshot::default(),
+            worktree: None,
+            session: agent.session.as_ref().map(|s| s.id.clone()),
+            dry_report: None,
+            report: None,
+            result: Err("agent crashed".to_string()),
+        }
+    }
+}
+
+/// Run several employees at once on their own tasks.
+/// Every agent claims the files it writes, so a second agent gets an error for them instead of overwriting.
+fn run_parallel(config: &Config, dir: &mut Dir, jobs: Vec<(String, String)>, shell: &mut Shell) -> Result<(), OrchestraError> {
+    let locks = FileLocks::default();
+    let board = StatusBoard::default();
+    let operator = Arc::new(Mutex::new(ShellOperator));
+
+    let mut agents = Vec::new();
+    for (name, task) in jobs {
+        let employee = config.employee(&name)?;
+        let promt = Promt::new(name.clone(), dir.clone(), config.employee.clone(), task.clone())?;
+        let mut options = exec_options(config, employee, shell);
+        options.operator = Some(Box::new(SharedOperator { agent: name.clone(), inner: Arc::clone(&operator) }));
+
+        let mut agent = Agent::new(promt, employee, build_provider(config, employee)?, options);
+        agent.budget = ContextBudget::from_config(&employee.llm.or(&config.llm));
+        agent.board = Some(board.entry(&name));
+        if !shell.dry_run {
+            let session = Session::new(&employee.dir, &name, &task);
+            println!("{}{}: session {}{}", BLUE, name, session.id, RESET);
+            agent.session = Some(session);
+        }
+
+        let (own, worktree) = match open_worktree(config, dir, &name, shell)? {
+            Some((worktree, own)) => {
+                if let Some(session) = &mut agent.session {
+                    session.worktree = Some(worktree.clone());
+                }
+                (own, Some(worktree))
+            }
+            None => {
+                let mut own = dir.clone();
+                own.locks = Some(locks.holder(&name));
+                (own, None)
+            }
+        };
+        agents.push((name, task, agent, own, worktree));
+    }
+
+    // Git checkpoints follow one agent per branch, a parallel run has none, only worktree branches
+    println!("{}Starting {} agents...{}", BLUE, agents.len(), RESET);
+    let finished: Vec<Finished> = std::thread::scope(|scope| {
+        let handles: Vec<_> = agents
+            .into_iter()
+            .map(|(name, task, mut agent, mut own, worktree)| {
+                let crashed = Finished::crashed(&name, &agent);
+                let handle = scope.spawn(move || {
+                    let result = agent.run(&mut own).map_err(|e| e.to_string());
+                    if let Some(locks) = &own.locks {
+                        locks.release();
+                    }
+                    // Undo restores files in the project dir, a worktree is undone by not merging it
+                    let journal = agent.take_journal();
+                    Finished {
+                        name,
+                        journal: if worktree.is_some() { Snapshot::default() } else { journal },
+                        worktree: worktree.map(|w| commit_worktree(&w, &task)),
+                        session: agent.session.as_ref().map(|s| s.id.clone()),
+                        dry_report: agent.dry_run().map(|dry| dry.report()),
+                        report: agent.report_path.clone(),
+                        result,
+                    }
+                });
+                (crashed, handle)
+            })
+            .collect();
+
+        let mut shown = String::new();
+        while !handles.iter().all(|(_, h)| h.is_finished()) {
+            // Quiet while an agent asks the operator
+            if operator.try_lock().is_ok() {
+                let line = board.line();
+                if line != shown {
+                    println!("{}⏳ {}{}", CYAN, line, RESET);
+                    shown = line;
+                }
+            }
+            std::thread::sleep(Duration::from_millis(300));
+        }
+        // A panic ends only its own agent, the others are reported as usual
+        handles
+            .into_iter()
+            .map(|(mut crashed, h)| {
+                h.join().unwrap_or_else(|panic| {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    board.entry(&crashed.name).set("crashed");
+                    crashed.result = Err(format!("agent crashed: {}, its changes cannot be undone", message));
+                    crashed
+                })
+            })
+            .collect()
+    });
+    println!("{}⏳ {}{}", CYAN, board.line(), RESET);
+
+    // One undo step for the whole run, the agents changed different files
+    let mut journal = Snapshot::default();
+    for done in &finished {
+        match &done.result {
+            Ok(()) => println!("{}{}: done{}", GREEN, done.name, RESET),
+            Err(e) => {
+                eprintln!("{}{}: {}{}", RED, done.name, e, RESET);
+                if let Some(id) = &done.session {
+                    eprintln!("{}Continue later with: resume {}{}", YELLOW, id, RESET);
+                }
+            }
+        }
+        if let Some(path) = &done.report {
+            println!("{}{} report: {}{}", BLUE, done.name, path.display(), RESET);
+        }
+        if let Some(line) = &done.worktree {
+            println!("{}", line);
+        }
+        if let Some(report) = &done.dry_report {
+            println!("{}=== Dry run of {}: nothing was written ==={}", BLUE, done.name, RESET);
+            println!("{}", report);
+        }
+    }
+    for done in finished {
+        journal.merge(done.journal);
+    }
+    if !journal.is_empty() {
+        shell.undo.push(journal);
+    }
+    dir.reload()?;
+    Ok(())
+}
+
+/// Continue a stored session of any employee
+fn resume_session(config: &Config, dir: &mut Dir, id: &str, shell: &mut Shell) -> Result<(), OrchestraError> {
+    let session = Session::load(config, id)?;
+    if matches!(session.state, SessionState::Finished | SessionState::Aborted) {
+        println!("{}Session {} is {:?}, nothing to resume{}", YELLOW, id, session.state, RESET);
+        return Ok(());
+    }
+    if shell.dry_run {
+        println!("{}Turn dryrun off to resume a session{}", YELLOW, RESET);
+        return Ok(());
+    }
+
+    let employee = config.employee(&session.agent)?;
+    let worktree = session.worktree.clone();
+    let mut agent = Agent::from_session(session, employee, build_provider(config, employee)?, exec_options(config, employee, shell));
+    match worktree {
+        Some(worktree) if !worktree.dir.is_dir() => Err(OrchestraError::Session(format!(
+            "worktree {} of this session was merged or removed", worktree.root.display()
+        ))),
+        Some(worktree) => {
+            let mut own = worktree_dir(config, &worktree)?;
+            run_agent(config, &mut own, employee, &mut agent, shell, Some(&worktree))
+        }
+        None => run_agent(config, dir, employee, &mut agent, shell, None),
+    }
+}
+
+fn build_provider(config: &Config, employee: &Employee) -> Result<Box<dyn ModelProvider>, OrchestraError> {
+    provider::from_config(&employee.llm.or(&config.llm)).map_err(OrchestraError::Provider)
+}
+
+fn exec_options(config: &Config, employee: &Employee, shell: &Shell) -> ExecOptions {
+    ExecOptions {
+        run_policy: config.policy.run.with_mode(employee.run_mode),
+        operator: Some(Box::new(ShellOperator)),
+        approval: shell.approval,
+        dry_run: shell.dry_run.then(DryRun::new),
+        journal: Snapshot::default(),
+        on_error: employee.on_error.unwrap_or(config.policy.on_error),
+        bus: Some(Box::new(shell.bus.mailbox(&employee.name))),
+    }
+}
+
+/// Run an agent in `dir`, which is the worktree checkout when `worktree` is set
+fn run_agent(config: &Config, dir: &mut Dir, employee: &Employee, agent: &mut Agent, shell: &mut Shell, worktree: Option<&Worktree>) -> Result<(), OrchestraError> {
+    agent.budget = ContextBudget::from_config(&employee.llm.or(&config.llm));
+    // A worktree gets one commit on its own branch at the end instead
+    if !shell.dry_run && worktree.is_none() {
+        match git::Checkpoints::start(&dir.path, &employee.name) {
+            Ok(Some(checkpoints)) => {
+                println!("{}Checkpoints on branch {}{}", BLUE, checkpoints.branch, RESET);
+                agent.checkpoints = Some(checkpoints);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("{}Checkpoints disabled: {}{}", YELLOW, e, RESET),
+        }
+    }
+    if let Some(session) = &agent.session {
+        println!("{}Session {}{}", BLUE, session.id, RESET);
+    }
 
     println!("{}Starting...{}", BLUE, RESET);
-    agent.run(dir)?;
+    let result = agent.run(dir);
+    if let Some(path) = &agent.report_path {
+        println!("{}Report: {}{}", BLUE, path.display(), RESET);
+    }
+
+    // Scripts which finished before a failure stay applied, keep them undoable
+    let journal = agent.take_journal();
+    match worktree {
+        Some(worktree) => {
+            let task = agent.session.as_ref().map(|s| s.task.clone()).unwrap_or_default();
+            println!("{}", commit_worktree(worktree, &task));
+        }
+        None if !journal.is_empty() => shell.undo.push(journal),
+        None => {}
+    }
+    if result.is_err()
+        && let Some(session) = &agent.session
+    {
+        eprintln!("{}Continue later with: resume {}{}", YELLOW, session.id, RESET);
+    }
+    result?;
+
+    if let Some(dry) = agent.dry_run() {
+        println!("{}=== Dry run: nothing was written ==={}", BLUE, RESET);
+        println!("{}", dry.report());
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Operator answering agent questions from the interactive shell
+struct ShellOperator;
+
+/// The shell operator for one agent of a parallel run, questions are asked one at a time
+struct SharedOperator {
+    agent: String,
+    inner: Arc<Mutex<ShellOperator>>,
+}
+
+impl Operator for SharedOperator {
+    fn confirm_run(&mut self, command: &str, reason: &str) -> bool {
+        let mut operator = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        println!("{}[{}]{}", PURPLE, self.agent, RESET);
+        operator.confirm_run(command, reason)
+    }
+
+    fn review(&mut self, action: &str, preview: &str) -> Review {
+        let mut operator = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        println!("{}[{}]{}", PURPLE, self.agent, RESET);
+        operator.review(action, preview)
+    }
+}
+
+impl Operator for ShellOperator {
+    fn confirm_run(&mut self, command: &str, reason: &str) -> bool {
+        println!("{}Agent wants to run:{} {} {}({}){}", YELLOW, RESET, command, PURPLE, reason, RESET);
+        print!("Allow? [y/N] > ");
+        read_line().is_some_and(|answer| matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    fn review(&mut self, action: &str, preview: &str) -> Review {
+        println!("{}Agent wants to:{} {}", YELLOW, RESET, action);
+        for line in preview.lines() {
+            let color = match line.chars().next() {
+                Some('+') if !line.starts_with("+++") => GREEN,
+                Some('-') if !line.starts_with("---") => RED,
+                Some('@') => CYAN,
+                _ => RESET,
+            };
+            println!("{}{}{}", color, line, RESET);
+        }
+
+        loop {
+            print!("[a]pprove / [s]kip / [e]dit / [q] abort > ");
+            io::stdout().flush().ok();
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+                return Review::Abort;
+            }
+
+            match answer.trim().to_lowercase().as_str() {
+                "a" | "approve" | "y" | "yes" => return Review::Approve,
+                "s" | "skip" | "n" | "no" => return Review::Skip,
+                "q" | "abort" => return Review::Abort,
+                "e" | "edit" => {
+                    println!("{}Type DSL commands to run instead, finish with an empty line:{}", YELLOW, RESET);
+                    let mut lines = Vec::new();
+                    loop {
+                        let mut line = String::new();
+                        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                            break;
+                        }
+                        lines.push(line.trim_end_matches(['\r', '\n']).to_string());
+                    }
+                    return Review::Edit(lines.join("\n"));
+                }
+                _ => continue,
+            }
+        }
+    }
+}
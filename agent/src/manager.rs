@@ -0,0 +1,400 @@
+use std::{fs, io::Write};
+use std::path::PathBuf;
+use chrono::Local;
+use crate::coderun::parser::{parse_script, CommandType};
+use crate::config::{Employee, Manager};
+use crate::filesystem::Dir;
+use crate::context::ContextBudget;
+use crate::bus::Bus;
+use crate::error::OrchestraError;
+use crate::model::Promt;
+use crate::provider::{Message, ModelProvider};
+
+/// Answers without a usable plan or verdict before the manager gives up
+const MAX_RETRIES: usize = 2;
+
+/// Runs one subtask with a team member: the report of its work, None when the operator aborted it
+pub type Delegate<'a> = dyn FnMut(&Member, &str) -> Result<Option<String>, OrchestraError> + 'a;
+
+/// Member of a manager's team, a lower manager runs its subtasks with its own team
+#[derive(Debug, Clone)]
+pub enum Member {
+    Employee(Box<Employee>),
+    Manager { name: String, role: String },
+}
+
+impl Member {
+    pub fn manager(manager: &Manager) -> Self {
+        Member::Manager {
+            name: manager.name.clone(),
+            role: format!("{} manager of {}", manager.level, manager.team.join(", ")),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Member::Employee(employee) => &employee.name,
+            Member::Manager { name, .. } => name,
+        }
+    }
+
+    pub fn role(&self) -> &str {
+        match self {
+            Member::Employee(employee) => &employee.task,
+            Member::Manager { role, .. } => role,
+        }
+    }
+}
+
+/// One subtask of the plan and how its review ended
+#[derive(Debug)]
+pub struct Subtask {
+    pub employee: String,
+    pub task: String,
+    pub attempts: u32,
+    pub accepted: bool,
+    /// The operator stopped the subtask, the manager stopped with it
+    pub aborted: bool,
+    /// Note of ACCEPT, or why the subtask was not accepted
+    pub note: String,
+    /// Report of the last attempt
+    pub report: String,
+}
+
+/// What a lower manager hands up as its report: every subtask with its verdict and last report
+pub fn team_report(manager: &str, subtasks: &[Subtask]) -> String {
+    let mut report = vec![format!("{} split the task into {} subtasks:", manager, subtasks.len())];
+    for (i, subtask) in subtasks.iter().enumerate() {
+        let verdict = if subtask.accepted { "accepted" } else { "not accepted" };
+        report.push(format!(
+            "{}. {} ({}, attempts: {}): {}\n   {}",
+            i + 1, subtask.employee, verdict, subtask.attempts,
+            subtask.task.lines().next().unwrap_or_default(), subtask.note
+        ));
+    }
+    for (i, subtask) in subtasks.iter().enumerate() {
+        report.push(format!("\n=== Subtask {} by {} ===\n{}", i + 1, subtask.employee, subtask.report));
+    }
+    report.join("\n")
+}
+
+enum Verdict {
+    Accept(String),
+    Revise(String),
+}
+
+/// Agent which splits a task into subtasks for its team, runs them and reviews the results
+pub struct ManagerAgent {
+    pub name: String,
+    pub task: String,
+    pub team: Vec<Member>,
+    /// Times a subtask is sent back before the manager moves on
+    pub max_revisions: u32,
+    pub log_path: PathBuf,
+    pub conversation_history: Vec<Message>,
+    pub provider: Box<dyn ModelProvider>,
+    pub budget: ContextBudget,
+    /// Inbox for NOTIFY of the agents
+    pub bus: Option<Box<dyn Bus>>,
+}
+
+impl ManagerAgent {
+    pub fn new(manager: &Manager, team: Vec<Member>, dir: &Dir, task: String, provider: Box<dyn ModelProvider>) -> Self {
+        let log_dir = PathBuf::from(&manager.dir).join("log");
+        fs::create_dir_all(&log_dir).ok();
+        let now = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+
+        let promt = Promt::for_manager(manager, &team, dir, task.clone());
+        let mut conversation_history = Vec::new();
+        if let Some(system) = promt.system {
+            conversation_history.push(Message::new("system", system));
+        }
+        if let Some(message) = promt.message {
+            conversation_history.push(Message::new("user", message));
+        }
+
+        Self {
+            name: manager.name.clone(),
+            task,
+            team,
+            max_revisions: manager.max_revisions.unwrap_or(2),
+            log_path: log_dir.join(format!("Manager_{}.log", now)),
+            conversation_history,
+            provider,
+            budget: ContextBudget::default(),
+            bus: None,
+        }
+    }
+
+    /// Plan, then run and review every subtask in order
+    pub fn run(&mut self, delegate: &mut Delegate) -> Result<Vec<Subtask>, OrchestraError> {
+        self.log_event(&format!("👔 Manager {} starting work...", self.name));
+
+        let plan = self.plan()?;
+        for (i, (member, task)) in plan.iter().enumerate() {
+            println!("📋 {}. {} → {}", i + 1, member.name(), task.lines().next().unwrap_or_default());
+        }
+
+        let mut done = Vec::new();
+        for (i, (member, task)) in plan.iter().enumerate() {
+            let mut subtask = Subtask {
+                employee: member.name().to_string(),
+                task: task.clone(),
+                attempts: 0,
+                accepted: false,
+                aborted: false,
+                note: String::new(),
+                report: String::new(),
+            };
+            let mut brief = self.brief(task, None);
+
+            loop {
+                subtask.attempts += 1;
+                println!("👷 Subtask {}/{} for {}, attempt {}", i + 1, plan.len(), member.name(), subtask.attempts);
+                self.log_event(&format!("👷 Subtask {} → {} (attempt {}):\n{}", i + 1, member.name(), subtask.attempts, brief));
+
+                let Some(report) = delegate(member, &brief)? else {
+                    self.log_event("🛑 Subtask aborted by operator, the manager stops.");
+                    subtask.aborted = true;
+                    subtask.note = "aborted by operator".to_string();
+                    done.push(subtask);
+                    return Ok(done);
+                };
+
+                let verdict = self.review(member, i + 1, plan.len(), subtask.attempts, &report)?;
+                subtask.report = report;
+                match verdict {
+                    Some(Verdict::Accept(note)) => {
+                        println!("✅ {} accepted the work of {}", self.name, member.name());
+                        subtask.accepted = true;
+                        subtask.note = note;
+                        break;
+                    }
+                    Some(Verdict::Revise(feedback)) if subtask.attempts <= self.max_revisions => {
+                        println!("🔁 {} sent the work back to {}: {}", self.name, member.name(), feedback);
+                        brief = self.brief(task, Some(&feedback));
+                    }
+                    Some(Verdict::Revise(feedback)) => {
+                        println!("⚠️ Revision limit reached for {}, moving on", member.name());
+                        subtask.note = format!("not accepted after {} attempts: {}", subtask.attempts, feedback);
+                        break;
+                    }
+                    None => {
+                        println!("⚠️ {} gave no verdict on the work of {}, moving on", self.name, member.name());
+                        subtask.note = format!("not accepted, {} answered without ACCEPT or REVISE", self.name);
+                        break;
+                    }
+                }
+            }
+            done.push(subtask);
+        }
+
+        self.log_event("🏁 Manager finished.");
+        Ok(done)
+    }
+
+    /// ASSIGN commands of the first answer, asked again while they name unknown members or are missing
+    fn plan(&mut self) -> Result<Vec<(Member, String)>, OrchestraError> {
+        for attempt in 0..=MAX_RETRIES {
+            let answer = self.ask(if attempt == 0 { "plan" } else { "fixed plan" })?;
+
+            let mut plan = Vec::new();
+            let mut problems = Vec::new();
+            for parsed in parse_script(&answer) {
+                match parsed {
+                    Ok(cmd) => match cmd.command_type {
+                        CommandType::Assign => {
+                            let target = cmd.target.unwrap_or_default();
+                            match self.member(&target) {
+                                Some(member) => plan.push((member.clone(), cmd.content.unwrap_or_default())),
+                                None => problems.push(format!("\"{}\" is not in your team", target)),
+                            }
+                        }
+                        CommandType::Comments => println!("💬 {}", cmd.body),
+                        _ => {}
+                    },
+                    Err(err) => problems.push(format!("parse error at {}", err)),
+                }
+            }
+            if plan.is_empty() {
+                problems.push("the plan has no ASSIGN".to_string());
+            }
+            if problems.is_empty() {
+                return Ok(plan);
+            }
+
+            self.log_event(&format!("❌ Plan rejected: {}", problems.join("; ")));
+            if attempt == MAX_RETRIES {
+                return Err(OrchestraError::Manager(format!("{} sent no usable plan: {}", self.name, problems.join("; "))));
+            }
+            self.conversation_history.push(Message::new(
+                "user",
+                format!(
+                    "=== Plan rejected ===\n{}\n\nSend the whole plan again, ASSIGN only to: {}.",
+                    problems.join("\n"),
+                    self.team.iter().map(|m| format!("\"{}\"", m.name())).collect::<Vec<_>>().join(", ")
+                ),
+            ));
+        }
+        unreachable!("the last attempt returns")
+    }
+
+    /// Show the report to the manager and read ACCEPT or REVISE, None when it sent neither
+    fn review(&mut self, member: &Member, n: usize, total: usize, attempt: u32, report: &str) -> Result<Option<Verdict>, OrchestraError> {
+        self.conversation_history.push(Message::new(
+            "user",
+            format!(
+                "=== Report of {} (subtask {}/{}, attempt {}) ===\n{}\n\nReview the work and answer with ACCEPT or REVISE.",
+                member.name(), n, total, attempt, report
+            ),
+        ));
+
+        for attempt in 0..=MAX_RETRIES {
+            let answer = self.ask("review")?;
+            for cmd in parse_script(&answer).into_iter().flatten() {
+                match cmd.command_type {
+                    CommandType::Accept => return Ok(Some(Verdict::Accept(cmd.body))),
+                    CommandType::Revise => return Ok(Some(Verdict::Revise(cmd.body))),
+                    CommandType::Comments => println!("💬 {}", cmd.body),
+                    _ => {}
+                }
+            }
+            if attempt < MAX_RETRIES {
+                self.conversation_history.push(Message::new("user", "Answer with exactly one ACCEPT or REVISE command."));
+            }
+        }
+
+        self.log_event("⚠️ No verdict from the manager, the subtask is left not accepted");
+        Ok(None)
+    }
+
+    /// Team member by name, or by role when the model named the role
+    fn member(&self, name: &str) -> Option<&Member> {
+        self.team
+            .iter()
+            .find(|m| m.name() == name)
+            .or_else(|| self.team.iter().find(|m| m.name().eq_ignore_ascii_case(name)))
+            .or_else(|| self.team.iter().find(|m| m.role().eq_ignore_ascii_case(name)))
+    }
+
+    /// Task text for the employee, with the whole task as context and the feedback of the last review
+    fn brief(&self, subtask: &str, feedback: Option<&str>) -> String {
+        let mut brief = format!(
+            "{}\n\n=== Context ===\nThis is a subtask from your manager {} for the task:\n{}",
+            subtask, self.name, self.task
+        );
+        if let Some(feedback) = feedback {
+            brief.push_str(&format!(
+                "\n\n=== Review feedback from {} ===\nYour previous attempt was sent back:\n{}",
+                self.name, feedback
+            ));
+        }
+        brief
+    }
+
+    fn ask(&mut self, what: &str) -> Result<String, OrchestraError> {
+        let notes = self.bus.as_mut().map(|bus| bus.inbox()).unwrap_or_default();
+        if !notes.is_empty()
+            && let Some(last) = self.conversation_history.last_mut()
+            && last.role == "user"
+        {
+            last.content.push_str(&format!("\n\n=== Messages from your team ===\n{}", notes.join("\n")));
+        }
+        if let Some(c) = self.budget.compact(&mut self.conversation_history) {
+            self.log_event(&format!("🗜️ Context compacted: ~{} → ~{} tokens", c.before, c.after));
+        }
+        self.log_event(&format!("🧠 Sending model request ({})", what));
+
+        let answer = self.provider.complete(&self.conversation_history).map_err(|err| {
+            self.log_event(&format!("❌ Model request failed: {}", err));
+            OrchestraError::Provider(err)
+        })?;
+        self.log_event(&format!("✅ Received {}:\n{}", what, answer));
+        self.conversation_history.push(Message::new("assistant", answer.clone()));
+        Ok(answer)
+    }
+
+    fn log_event(&self, message: &str) {
+        let timestamp = Local::now().format("%H:%M:%S").to_string();
+        let log_line = format!("[{}] {}\n", timestamp, message);
+
+        if let Ok(mut file) = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)
+        {
+            let _ = file.write_all(log_line.as_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LlmConfig;
+    use crate::provider::mock::MockProvider;
+
+    fn manager(root: &std::path::Path, team: &[&str]) -> Manager {
+        Manager {
+            dir: root.join("emma").display().to_string(),
+            name: "Emma".to_string(),
+            level: "lead".to_string(),
+            team: team.iter().map(|s| s.to_string()).collect(),
+            max_revisions: None,
+            llm: LlmConfig::default(),
+        }
+    }
+
+    fn run(test: &str, team: Vec<Member>, script: &str) -> (Vec<Subtask>, Vec<String>) {
+        let root = std::env::temp_dir().join(format!("orchestra-manager-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let names: Vec<&str> = team.iter().map(|m| m.name()).collect();
+        let manager = manager(&root, &names);
+        let dir = Dir::read_from_path(&root).unwrap();
+
+        let provider = Box::new(MockProvider::from_script(script, None));
+        let mut boss = ManagerAgent::new(&manager, team, &dir, "Build it".to_string(), provider);
+        let mut delegated = Vec::new();
+        let subtasks = boss
+            .run(&mut |member, _| {
+                delegated.push(member.name().to_string());
+                Ok(Some(format!("{} did it", member.name())))
+            })
+            .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        (subtasks, delegated)
+    }
+
+    fn employee(name: &str) -> Member {
+        Member::Employee(Box::new(Employee {
+            dir: String::new(),
+            name: name.to_string(),
+            task: "developer".to_string(),
+            run_mode: None,
+            on_error: None,
+            llm: LlmConfig::default(),
+        }))
+    }
+
+    #[test]
+    fn no_verdict_leaves_the_subtask_not_accepted() {
+        let script = "ASSIGN \"Alex\" \"x\"\n---8<---\nCOMMENTS \"hm\"\n---8<---\nCOMMENTS \"hm\"\n---8<---\nlooks fine";
+        let (subtasks, _) = run("no-verdict", vec![employee("Alex")], script);
+        assert!(!subtasks[0].accepted);
+        assert!(subtasks[0].note.contains("without ACCEPT or REVISE"), "{}", subtasks[0].note);
+        assert_eq!(subtasks[0].report, "Alex did it");
+    }
+
+    #[test]
+    fn managers_in_the_team_get_subtasks() {
+        let john = Member::Manager { name: "John".to_string(), role: "senior manager of Alex".to_string() };
+        let script = "ASSIGN \"senior manager of Alex\" \"x\"\nASSIGN \"alex\" \"y\"\n---8<---\nACCEPT \"ok\"\n---8<---\nACCEPT \"ok\"";
+        let (subtasks, delegated) = run("team", vec![john, employee("Alex")], script);
+        assert_eq!(delegated, ["John", "Alex"]);
+        assert!(subtasks.iter().all(|s| s.accepted));
+
+        let report = team_report("Emma", &subtasks);
+        assert!(report.contains("1. John (accepted, attempts: 1): x"), "{}", report);
+        assert!(report.contains("=== Subtask 2 by Alex ===\nAlex did it"), "{}", report);
+    }
+}
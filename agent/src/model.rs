@@ -1,18 +1,31 @@
 use crate::filesystem::Dir;
-use crate::config::Employee;
+use crate::config::{Employee, Manager};
+use crate::error::OrchestraError;
+use crate::manager::Member;
 
 #[derive(Debug)]
 pub struct Promt {
     pub message: Option<String>,
-    pub system: Option<String>
+    pub system: Option<String>,
+    /// Task as the operator or manager wrote it, for reports
+    pub task: String,
 }
 
 impl Promt {
-    pub fn new(curent_employee: String, dir: Dir, employee: Vec<Employee>, task_msg: String) -> Self {
+    pub fn new(curent_employee: String, dir: Dir, employee: Vec<Employee>, task_msg: String) -> Result<Self, OrchestraError> {
+        let known: Vec<String> = employee.iter().map(|e| e.name.clone()).collect();
+        let team: Vec<String> = employee
+            .iter()
+            .filter(|x| x.name != curent_employee)
+            .map(|x| format!("{} ({})", x.name, x.task))
+            .collect();
         let c_employee: Vec<Employee> = employee
             .into_iter()
             .filter(|x| x.name == curent_employee)
             .collect();
+        if c_employee.is_empty() {
+            return Err(OrchestraError::UnknownEmployee { name: curent_employee, known });
+        }
         
         let file_system_messege: String = dir.pretty_print();
         
@@ -23,10 +36,12 @@ Role: {2}
 LogDir: "{1}/log"
 ReportDir: "{1}/report"
 Comment Mark: [{0}] use syntax for language in request and if need in function and class
+Team: {3}
 "#,
             c_employee[0].name,
             c_employee[0].dir,
-            c_employee[0].task
+            c_employee[0].task,
+            if team.is_empty() { "nobody else".to_string() } else { team.join(", ") }
         );
         
         let task = format!("=== Task ===\n{}\n", task_msg);
@@ -40,11 +55,23 @@ Comment Mark: [{0}] use syntax for language in request and if need in function a
 📄 FILE:
   OPEN FILE "path"         - view file content
   CREATE FILE "path"       - create new file
+  CREATE FILE "path" WITH "text" - create file with content
   DELETE FILE "path" LINE n - delete line from file
 
 ✏️ EDIT:
   EDIT FILE "path" LINE n PUT "text"     - replace line n
   INSERT FILE "path" LINE n INSERT "text" - insert at line n (shifts down)
+  REPLACE FILE "path" LINES a-b WITH "text" - replace lines a..b (inclusive)
+  DELETE FILE "path" LINES a-b           - delete lines a..b (inclusive)
+  PATCH FILE "path" FIND "old" REPLACE "new" - replace exact text, must match once (preferred over line numbers)
+
+📦 BLOCKS (multi-line content, ends with a line holding only the tag):
+  INSERT FILE "path" LINE n <<<EOF       - insert all block lines at line n
+  EDIT FILE "path" LINE n PUT <<<EOF     - replace line n with all block lines
+  CREATE FILE "path" WITH <<<EOF         - create file with block content
+  REPLACE FILE "path" LINES a-b WITH <<<EOF - replace lines a..b with all block lines
+  PATCH FILE "path" FIND <<<OLD REPLACE <<<NEW - two blocks, OLD body first, then NEW body
+  APPLY DIFF <<<EOF                      - apply a unified diff (--- a/x, +++ b/x, @@ hunks) to the project
 
 🔧 EXECUTION:
   RUN "command"            - execute shell command
@@ -53,12 +80,19 @@ Comment Mark: [{0}] use syntax for language in request and if need in function a
   COMMENTS "text"          - document current action
   CALLBACK "message"       - report completion/next step
 
+🤝 TEAM:
+  ASK "name" "question"    - ask a team member or manager, the answer comes back with the results
+  NOTIFY "name" "message"  - leave a message for a team member, no answer
+  ASK "name" <<<EOF        - block for long questions, e.g. with code to review
+
 === Rules ===
 1. ALWAYS use CALLBACK after read operations (OPEN)
 2. ALWAYS use CALLBACK before write operations (EDIT, INSERT, DELETE, CREATE)
 3. Mark code with comment [YourName]
 4. Open files/dirs before editing
 5. Be concise - no explanations, just actions
+6. Escape quotes inside strings as \" and backslashes as \\
+7. Team members do not see the files, put the code into ASK; use CALLBACK after ASK to read the answer
 
 === Example ===
 COMMENTS "Opening project structure"
@@ -74,8 +108,10 @@ INSERT FILE "project/main.py" LINE 1 INSERT "import math  #[Agent]"
 CALLBACK "Import added, will add function"
 
 COMMENTS "Creating calculate function"
-EDIT FILE "project/main.py" LINE 3 PUT "def calculate(x):  //[Agent]"
-INSERT FILE "project/main.py" LINE 4 INSERT "    return math.sqrt(x)  #[Agent]"
+INSERT FILE "project/main.py" LINE 3 <<<EOF
+def calculate(x):  #[Agent]
+    return math.sqrt(x)
+EOF
 CALLBACK "Function ready, will test"
 
 COMMENTS "Running tests"
@@ -90,9 +126,72 @@ CALLBACK "Tests passed, task complete"
             commands,
         );
 
+        Ok(Promt {
+            message: Some(task.trim().to_string()),
+            system:Some(promt.trim().to_string()),
+            task: task_msg,
+        })
+    }
+    /// Prompt of a manager: the project, the team with their roles and the commands to plan and review
+    pub fn for_manager(manager: &Manager, team: &[Member], dir: &Dir, task_msg: String) -> Self {
+        let file_system_messege: String = dir.pretty_print();
+
+        let roster: Vec<String> = team
+            .iter()
+            .map(|m| format!("- \"{}\": {}", m.name(), m.role()))
+            .collect();
+
+        let manager_info = format!(
+            r#"=== Manager Info ===
+Name: {0}
+Level: {1}
+You lead a team of agents. You do not change the project yourself: you split the task into subtasks,
+give each one to the team member whose role fits, and review what they did.
+
+=== Team ===
+{2}
+"#,
+            manager.name,
+            manager.level,
+            roster.join("\n")
+        );
+
+        let task = format!("=== Task ===\n{}\n", task_msg);
+
+        let commands = r#"=== Manager Commands ===
+
+  ASSIGN "name" "subtask"     - give a subtask to a team member
+  ASSIGN "name" <<<EOF        - same, multi-line subtask ending with a line holding only EOF
+  COMMENTS "text"             - document your reasoning
+  ACCEPT "note"               - the reviewed work is done
+  REVISE "feedback"           - send the reviewed work back, say exactly what to fix (<<<EOF blocks work too)
+
+=== Rules ===
+1. First answer with the plan: ASSIGN commands in the order they must run, nothing else changes the project
+2. Each subtask must be complete on its own: name files, functions and expected behaviour
+3. After every subtask you get the report of the team member, answer with exactly one ACCEPT or REVISE
+4. REVISE only for real problems, the team member starts again with your feedback
+5. Escape quotes inside strings as \" and backslashes as \\
+
+=== Example ===
+COMMENTS "API first, then tests"
+ASSIGN "Alex" <<<EOF
+Add GET /health to src/server.py returning {"status": "ok"}
+EOF
+ASSIGN "Emma" "Write tests for GET /health in tests/test_server.py and run them"
+"#;
+
+        let promt = format!(
+            "{}\n{}\n{}",
+            file_system_messege,
+            manager_info,
+            commands,
+        );
+
         Promt {
             message: Some(task.trim().to_string()),
-            system:Some(promt.trim().to_string())
+            system: Some(promt.trim().to_string()),
+            task: task_msg,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,165 @@
+use std::error::Error;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::config::LlmConfig;
+use super::error::{check_response, ErrorKind, ProviderError};
+use super::{http_client, Message, ModelProvider, Usage};
+
+const API_VERSION: &str = "2023-06-01";
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+pub const DEFAULT_MODEL: &str = "claude-sonnet-4-5";
+
+#[derive(Serialize)]
+struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<&'a Message>,
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct TokenUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+/// Anthropic answers with a list of typed blocks, we only care about text ones
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// Anthropic Messages API backend
+pub struct AnthropicProvider {
+    client: Client,
+    model: String,
+    base_url: String,
+    temperature: Option<f32>,
+    max_tokens: u32,
+    api_key_env: String,
+    usage: Usage,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: &LlmConfig) -> Result<Self, ProviderError> {
+        Ok(AnthropicProvider {
+            client: http_client(config)?,
+            model: config.model.clone().unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            base_url: config.base_url.clone().unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            temperature: config.temperature,
+            // max_tokens is mandatory for the Messages API
+            max_tokens: config.max_tokens.unwrap_or(4096),
+            api_key_env: config.api_key_env.clone().unwrap_or_else(|| "ANTHROPIC_API_KEY".to_string()),
+            usage: Usage::default(),
+        })
+    }
+}
+
+impl ModelProvider for AnthropicProvider {
+    fn complete(&mut self, messages: &[Message]) -> Result<String, Box<dyn Error>> {
+        let api_key = std::env::var(&self.api_key_env).map_err(|_| {
+            ProviderError::new(ErrorKind::Auth, format!("{} environment variable not set", self.api_key_env))
+        })?;
+
+        let (system, messages) = split_system(messages);
+        let request = MessagesRequest {
+            model: &self.model,
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            system,
+            messages,
+        };
+
+        let res = self.client
+            .post(format!("{}/messages", self.base_url.trim_end_matches('/')))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&request)
+            .send()
+            .map_err(|e| ProviderError::from_reqwest("Anthropic", e))?;
+        let res = check_response("Anthropic", res)?;
+
+        let body: MessagesResponse = res.json().map_err(|e| ProviderError::from_reqwest("Anthropic", e))?;
+        if let Some(usage) = &body.usage {
+            self.usage.add(usage.input_tokens, usage.output_tokens);
+        }
+        let script = response_text(&body.content);
+
+        if script.trim().is_empty() {
+            return Ok("COMMENTS \"No script generated\"".to_string());
+        }
+
+        Ok(script)
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage
+    }
+}
+
+/// The Messages API has no "system" role, the system prompt goes to the top-level field
+fn split_system(messages: &[Message]) -> (Option<String>, Vec<&Message>) {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+    let system = if system.is_empty() { None } else { Some(system.join("\n\n")) };
+    (system, messages.iter().filter(|m| m.role != "system").collect())
+}
+
+fn response_text(blocks: &[ContentBlock]) -> String {
+    blocks
+        .iter()
+        .filter(|block| block.kind == "text")
+        .map(|block| block.text.as_str())
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_messages_go_to_the_top_level_field() {
+        let messages = vec![
+            Message::new("system", "rules"),
+            Message::new("user", "task"),
+            Message::new("system", "more rules"),
+            Message::new("assistant", "RUN \"ls\""),
+        ];
+        let (system, rest) = split_system(&messages);
+        assert_eq!(system.as_deref(), Some("rules\n\nmore rules"));
+        let roles: Vec<&str> = rest.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant"]);
+
+        let (system, rest) = split_system(&messages[1..2]);
+        assert!(system.is_none());
+        assert_eq!(rest.len(), 1);
+    }
+
+    #[test]
+    fn only_text_blocks_make_the_script() {
+        let body = r#"{"content":[
+            {"type":"text","text":"RUN \"ls\"\n"},
+            {"type":"tool_use","id":"x","name":"y","input":{}},
+            {"type":"text","text":"COMMENTS \"done\""}
+        ]}"#;
+        let response: MessagesResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(response_text(&response.content), "RUN \"ls\"\nCOMMENTS \"done\"");
+    }
+}
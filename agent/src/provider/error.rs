@@ -0,0 +1,125 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::blocking::Response;
+use reqwest::StatusCode;
+
+/// Class of a failed model request, only Transient ones are retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    /// Missing or rejected API key
+    Auth,
+    /// Billing or quota exhausted, waiting does not help
+    Quota,
+    /// Rate limits, overload, 5xx, timeouts and dropped connections
+    Transient,
+    /// Request rejected as invalid or response we could not read
+    Malformed,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::Auth => "auth",
+            ErrorKind::Quota => "quota",
+            ErrorKind::Transient => "transient",
+            ErrorKind::Malformed => "malformed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ProviderError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Delay asked by the server via Retry-After
+    pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error: {}", self.kind, self.message)
+    }
+}
+
+impl Error for ProviderError {}
+
+impl ProviderError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        ProviderError { kind, message: message.into(), retry_after: None }
+    }
+
+    /// Failure of reqwest, only timeouts and failed connections or sends are worth another try.
+    /// A bad URL or a request which cannot be built fails the same way every time.
+    pub fn from_reqwest(provider: &str, err: reqwest::Error) -> Self {
+        let kind = if err.is_timeout() || err.is_connect() || err.is_request() {
+            ErrorKind::Transient
+        } else {
+            ErrorKind::Malformed
+        };
+        ProviderError::new(kind, format!("{}: {}", provider, err))
+    }
+}
+
+/// `Retry-After: 20` or OpenAI's `retry-after-ms: 1500`, HTTP dates are ignored
+fn retry_after(res: &Response) -> Option<Duration> {
+    let header = |name: &str| res.headers().get(name)?.to_str().ok()?.trim().parse::<f64>().ok();
+    header("retry-after-ms")
+        .map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0))
+        .or_else(|| header("retry-after").map(|s| Duration::from_secs_f64(s.max(0.0))))
+}
+
+/// Turn a non-2xx answer into a classified error, 2xx answers pass through
+pub fn check_response(provider: &str, res: Response) -> Result<Response, ProviderError> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+
+    let retry_after = retry_after(&res);
+    let body = res.text().unwrap_or_default();
+    let quota = body.contains("insufficient_quota") || body.contains("credit balance");
+
+    let kind = match status.as_u16() {
+        401 | 403 => ErrorKind::Auth,
+        402 => ErrorKind::Quota,
+        429 if quota => ErrorKind::Quota,
+        // 529 is Anthropic's "overloaded"
+        408 | 409 | 429 | 529 => ErrorKind::Transient,
+        _ if status.is_server_error() => ErrorKind::Transient,
+        _ if quota => ErrorKind::Quota,
+        _ => ErrorKind::Malformed,
+    };
+
+    let detail: String = body.chars().take(300).collect();
+    Err(ProviderError {
+        kind,
+        message: format!("{} answered {}: {}", provider, status_text(status), detail.trim()),
+        retry_after,
+    })
+}
+
+fn status_text(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) => format!("{} {}", status.as_u16(), reason),
+        None => status.as_u16().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_network_failures_are_transient() {
+        let client = reqwest::blocking::Client::new();
+
+        let refused = client.get("http://127.0.0.1:9").send().unwrap_err();
+        assert_eq!(ProviderError::from_reqwest("test", refused).kind, ErrorKind::Transient);
+
+        let bad_url = client.get("not a url").send().unwrap_err();
+        assert_eq!(ProviderError::from_reqwest("test", bad_url).kind, ErrorKind::Malformed);
+    }
+}
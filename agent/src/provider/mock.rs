@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::context::{estimate_tokens, history_tokens};
+use super::{Message, ModelProvider, Usage};
+
+/// Line which separates two canned responses in the responses file
+pub const TURN_SEPARATOR: &str = "---8<---";
+
+#[derive(Serialize)]
+struct RecordedRequest<'a> {
+    turn: usize,
+    messages: &'a [Message],
+}
+
+/// Offline backend which replays canned DSL scripts, one per turn, and records every request it got.
+/// Used to drive Agent::run end-to-end without network.
+pub struct MockProvider {
+    responses: VecDeque<String>,
+    record: Option<PathBuf>,
+    turn: usize,
+    /// Estimated, there is no tokenizer offline
+    usage: Usage,
+}
+
+impl MockProvider {
+    /// Load responses from a file where turns are separated by a `---8<---` line
+    pub fn from_file<P: AsRef<Path>>(path: P, record: Option<PathBuf>) -> std::io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::from_script(&content, record))
+    }
+
+    pub fn from_script(content: &str, record: Option<PathBuf>) -> Self {
+        let mut responses = VecDeque::new();
+        let mut current = Vec::new();
+
+        for line in content.lines() {
+            if line.trim() == TURN_SEPARATOR {
+                responses.push_back(current.join("\n"));
+                current.clear();
+            } else {
+                current.push(line);
+            }
+        }
+        if current.iter().any(|l| !l.trim().is_empty()) {
+            responses.push_back(current.join("\n"));
+        }
+
+        MockProvider {
+            responses,
+            record,
+            turn: 0,
+            usage: Usage::default(),
+        }
+    }
+
+    fn record_request(&self, messages: &[Message]) -> std::io::Result<()> {
+        let Some(path) = &self.record else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let line = serde_json::to_string(&RecordedRequest { turn: self.turn, messages })?;
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+impl ModelProvider for MockProvider {
+    fn complete(&mut self, messages: &[Message]) -> Result<String, Box<dyn Error>> {
+        self.turn += 1;
+        self.record_request(messages)?;
+
+        let script = self.responses
+            .pop_front()
+            .ok_or_else(|| format!("Mock provider has no response for turn {}", self.turn))?;
+        self.usage.add(history_tokens(messages) as u64, estimate_tokens(&script) as u64);
+        Ok(script)
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage
+    }
+}
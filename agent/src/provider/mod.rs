@@ -0,0 +1,145 @@
+use std::error::Error;
+use std::time::Duration;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::config::LlmConfig;
+
+pub mod openai;
+pub mod anthropic;
+pub mod mock;
+pub mod error;
+pub mod retry;
+
+pub use openai::OpenAiProvider;
+pub use anthropic::AnthropicProvider;
+pub use mock::MockProvider;
+pub use retry::RetryProvider;
+use error::{ErrorKind, ProviderError};
+
+/// One entry of the conversation, in the role/content shape every backend understands
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Message {
+            role: role.to_string(),
+            content: content.into(),
+        }
+    }
+}
+
+/// Tokens spent on the requests of one provider
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Usage {
+    pub fn add(&mut self, input_tokens: u64, output_tokens: u64) {
+        self.requests += 1;
+        self.input_tokens += input_tokens;
+        self.output_tokens += output_tokens;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            requests: self.requests + other.requests,
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+        }
+    }
+}
+
+/// Backend which turns the conversation into the next DSL script.
+/// The agent loop only talks to this trait, so backends and test doubles can be swapped freely.
+/// Send, because agents of a parallel run live on their own threads.
+pub trait ModelProvider: Send {
+    fn complete(&mut self, messages: &[Message]) -> Result<String, Box<dyn Error>>;
+
+    /// Tokens of every successful request so far, as the backend counted them
+    fn usage(&self) -> Usage {
+        Usage::default()
+    }
+}
+
+/// HTTP client with the request timeout of the config, a hung request counts as transient error.
+/// Fails when the TLS backend cannot be set up, e.g. without system certificates.
+fn http_client(config: &LlmConfig) -> Result<Client, ProviderError> {
+    Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs.unwrap_or(120)))
+        .connect_timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| ProviderError::new(ErrorKind::Malformed, format!("cannot build HTTP client: {}", e)))
+}
+
+/// Build the backend described by the merged [llm]/[[employee]] settings.
+/// Without an explicit provider OpenAI stays the default, Anthropic is used when only ANTHROPIC_API_KEY is set.
+pub fn from_config(config: &LlmConfig) -> Result<Box<dyn ModelProvider>, Box<dyn Error>> {
+    let name = provider_name(
+        config.provider.as_deref(),
+        std::env::var("OPENAI_API_KEY").is_ok(),
+        std::env::var("ANTHROPIC_API_KEY").is_ok(),
+    );
+
+    match name.as_str() {
+        "openai" => Ok(Box::new(RetryProvider::new(Box::new(OpenAiProvider::new(config)?), config))),
+        "anthropic" => Ok(Box::new(RetryProvider::new(Box::new(AnthropicProvider::new(config)?), config))),
+        "mock" => {
+            let responses = config.responses.as_ref()
+                .ok_or("Mock provider needs `responses` with the path to canned scripts")?;
+            let record = config.record.as_ref().map(std::path::PathBuf::from);
+            Ok(Box::new(MockProvider::from_file(responses, record)?))
+        }
+        other => Err(format!("Unknown provider \"{}\" (expected openai, anthropic or mock)", other).into()),
+    }
+}
+
+fn provider_name(provider: Option<&str>, has_openai: bool, has_anthropic: bool) -> String {
+    match provider {
+        Some(name) => name.to_lowercase(),
+        None if !has_openai && has_anthropic => "anthropic".to_string(),
+        None => "openai".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_provider_wins_over_keys() {
+        assert_eq!(provider_name(Some("Anthropic"), true, false), "anthropic");
+        assert_eq!(provider_name(Some("openai"), false, true), "openai");
+    }
+
+    #[test]
+    fn key_picks_the_default_provider() {
+        assert_eq!(provider_name(None, false, false), "openai");
+        assert_eq!(provider_name(None, true, true), "openai");
+        assert_eq!(provider_name(None, false, true), "anthropic");
+    }
+
+    #[test]
+    fn unknown_provider_is_an_error() {
+        let config = LlmConfig { provider: Some("gemini".to_string()), ..LlmConfig::default() };
+        let err = from_config(&config).err().unwrap();
+        assert!(err.to_string().contains("Unknown provider \"gemini\""));
+        let config = LlmConfig { provider: Some("anthropic".to_string()), ..LlmConfig::default() };
+        assert!(from_config(&config).is_ok());
+    }
+}
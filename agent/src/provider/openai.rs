@@ -0,0 +1,137 @@
+use std::error::Error;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::config::LlmConfig;
+use super::error::{check_response, ErrorKind, ProviderError};
+use super::{http_client, Message, ModelProvider, Usage};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+#[derive(Serialize)]
+struct GptRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct GptResponse {
+    choices: Vec<GptChoice>,
+    /// Missing on some self-hosted servers
+    usage: Option<GptUsage>,
+}
+
+#[derive(Deserialize)]
+struct GptUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct GptChoice {
+    message: GptChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct GptChoiceMessage {
+    content: String,
+}
+
+/// OpenAI chat completions backend, also works for self-hosted OpenAI-compatible servers via base_url
+pub struct OpenAiProvider {
+    client: Client,
+    model: String,
+    base_url: String,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    api_key_env: String,
+    usage: Usage,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: &LlmConfig) -> Result<Self, ProviderError> {
+        Ok(OpenAiProvider {
+            client: http_client(config)?,
+            model: config.model.clone().unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            base_url: config.base_url.clone().unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+            api_key_env: config.api_key_env.clone().unwrap_or_else(|| "OPENAI_API_KEY".to_string()),
+            usage: Usage::default(),
+        })
+    }
+}
+
+impl ModelProvider for OpenAiProvider {
+    fn complete(&mut self, messages: &[Message]) -> Result<String, Box<dyn Error>> {
+        let api_key = std::env::var(&self.api_key_env).ok();
+        // Self-hosted servers usually run without auth, api.openai.com never does
+        if api_key.is_none() && self.base_url == DEFAULT_BASE_URL {
+            return Err(ProviderError::new(ErrorKind::Auth, format!("{} environment variable not set", self.api_key_env)).into());
+        }
+
+        let request = GptRequest {
+            model: &self.model,
+            messages,
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+        };
+
+        let mut req = self.client
+            .post(format!("{}/chat/completions", self.base_url.trim_end_matches('/')))
+            .json(&request);
+        if let Some(key) = api_key {
+            req = req.bearer_auth(key);
+        }
+
+        let res = req.send().map_err(|e| ProviderError::from_reqwest("OpenAI", e))?;
+        let res = check_response("OpenAI", res)?;
+
+        let body: GptResponse = res.json().map_err(|e| ProviderError::from_reqwest("OpenAI", e))?;
+        if let Some(usage) = &body.usage {
+            self.usage.add(usage.prompt_tokens, usage.completion_tokens);
+        }
+        let script = body.choices.first()
+            .map(|c| c.message.content.clone())
+            .unwrap_or_else(|| "COMMENTS \"No script generated\"".to_string());
+
+        Ok(script)
+    }
+
+    fn usage(&self) -> Usage {
+        self.usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_carries_model_and_messages() {
+        let messages = vec![Message::new("system", "rules"), Message::new("user", "task")];
+        let request = GptRequest {
+            model: "gpt-4o-mini",
+            messages: &messages,
+            temperature: Some(0.2),
+            max_tokens: None,
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["model"], "gpt-4o-mini");
+        assert_eq!(json["messages"][0]["role"], "system");
+        assert_eq!(json["messages"][1]["content"], "task");
+        assert!(json.get("max_tokens").is_none());
+    }
+
+    #[test]
+    fn response_yields_first_choice() {
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"RUN \"ls\""}}]}"#;
+        let response: GptResponse = serde_json::from_str(body).unwrap();
+        assert_eq!(response.choices[0].message.content, "RUN \"ls\"");
+    }
+}
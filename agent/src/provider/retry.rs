@@ -0,0 +1,113 @@
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::LlmConfig;
+use super::error::{ErrorKind, ProviderError};
+use super::{Message, ModelProvider, Usage};
+
+/// Retries transient failures of another provider with exponential backoff and jitter
+pub struct RetryProvider {
+    inner: Box<dyn ModelProvider>,
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryProvider {
+    pub fn new(inner: Box<dyn ModelProvider>, config: &LlmConfig) -> Self {
+        RetryProvider {
+            inner,
+            max_retries: config.max_retries.unwrap_or(4),
+            base_delay: Duration::from_millis(config.retry_base_ms.unwrap_or(1000)),
+            max_delay: Duration::from_millis(config.retry_max_ms.unwrap_or(60_000)),
+        }
+    }
+
+    /// base * 2^attempt capped at max_delay, then a random point in its upper half
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        let delay = exp.min(self.max_delay).as_millis() as u64;
+        let random = RandomState::new().build_hasher().finish();
+        Duration::from_millis(delay / 2 + random % (delay / 2 + 1))
+    }
+}
+
+impl ModelProvider for RetryProvider {
+    fn complete(&mut self, messages: &[Message]) -> Result<String, Box<dyn Error>> {
+        let mut attempt = 0;
+        loop {
+            let err = match self.inner.complete(messages) {
+                Ok(script) => return Ok(script),
+                Err(err) => err,
+            };
+
+            let (kind, retry_after) = match err.downcast_ref::<ProviderError>() {
+                Some(e) => (e.kind, e.retry_after),
+                None => (ErrorKind::Malformed, None),
+            };
+            if kind != ErrorKind::Transient || attempt >= self.max_retries {
+                return Err(err);
+            }
+
+            // The server knows best when it is ready again, but never longer than retry_max_ms
+            let delay = retry_after.map_or_else(|| self.backoff(attempt), |after| after.min(self.max_delay));
+            attempt += 1;
+            eprintln!(
+                "⏳ {}, retry {}/{} in {:.1}s",
+                err,
+                attempt,
+                self.max_retries,
+                delay.as_secs_f64()
+            );
+            thread::sleep(delay);
+        }
+    }
+
+    fn usage(&self) -> Usage {
+        self.inner.usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Fails with a transient error asking for an hour of patience, then answers
+    struct Overloaded {
+        failures: u32,
+    }
+
+    impl ModelProvider for Overloaded {
+        fn complete(&mut self, _: &[Message]) -> Result<String, Box<dyn Error>> {
+            if self.failures == 0 {
+                return Ok("COMMENTS \"ok\"".to_string());
+            }
+            self.failures -= 1;
+            let mut err = ProviderError::new(ErrorKind::Transient, "overloaded");
+            err.retry_after = Some(Duration::from_secs(3600));
+            Err(Box::new(err))
+        }
+    }
+
+    #[test]
+    fn retry_after_is_clamped_to_the_max_delay() {
+        let config = LlmConfig { max_retries: Some(2), retry_max_ms: Some(10), ..LlmConfig::default() };
+        let mut provider = RetryProvider::new(Box::new(Overloaded { failures: 2 }), &config);
+
+        let started = Instant::now();
+        assert_eq!(provider.complete(&[]).unwrap(), "COMMENTS \"ok\"");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let config = LlmConfig { max_retries: Some(1), retry_max_ms: Some(1), ..LlmConfig::default() };
+        let mut provider = RetryProvider::new(Box::new(Overloaded { failures: 2 }), &config);
+        assert!(provider.complete(&[]).is_err());
+    }
+}
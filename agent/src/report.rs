@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::coderun::diff::HunkResult;
+use crate::coderun::parser::{Status, CMD};
+use crate::filesystem::PatchOutcome;
+use crate::provider::Usage;
+use crate::session::SessionState;
+
+/// Summary of one agent run, written as `<employee dir>/report/<id>.md` and `<id>.json`
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub agent: String,
+    pub task: String,
+    pub session: Option<String>,
+    pub state: SessionState,
+    pub dry_run: bool,
+    pub finished: String,
+    pub scripts: usize,
+    pub files: Vec<FileChange>,
+    pub commands: Vec<CommandRun>,
+    pub errors: Vec<String>,
+    /// CALLBACK of the last script which had one
+    pub callback: Option<String>,
+    pub usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    /// created, created dir or edited
+    pub change: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandRun {
+    pub command: String,
+    pub exit_code: i32,
+}
+
+impl Report {
+    /// Collect the results of every executed script, changes of rolled back scripts are left out
+    pub fn new(agent: &str, task: &str, state: SessionState, executed: &[Status], usage: Usage) -> Self {
+        let mut files: BTreeMap<String, &str> = BTreeMap::new();
+        let mut commands = Vec::new();
+        let mut errors = Vec::new();
+        let mut callback = None;
+
+        for status in executed {
+            let rolled_back = status.running.iter().any(|cmd| matches!(cmd, CMD::RolledBack { .. }));
+            for cmd in &status.running {
+                let changed = match cmd {
+                    CMD::CreateFile(path) => Some((path, "created")),
+                    CMD::CreateDir(path) => Some((path, "created dir")),
+                    CMD::EditFile { path, .. }
+                    | CMD::InsertFile { path, .. }
+                    | CMD::DeleteFile { path, .. }
+                    | CMD::ReplaceLines { path, .. }
+                    | CMD::DeleteLines { path, .. }
+                    | CMD::Patch { path, outcome: PatchOutcome::Applied { .. } } => Some((path, "edited")),
+                    _ => None,
+                };
+                if let Some((path, change)) = changed
+                    && !rolled_back
+                {
+                    // The first change wins, a created and then edited file is still new
+                    files.entry(path.clone()).or_insert(change);
+                }
+
+                match cmd {
+                    CMD::ApplyDiff { files: reports } if !rolled_back => {
+                        for report in reports {
+                            if report.error.is_none() && report.hunks.iter().any(|h| matches!(h, HunkResult::Applied { .. })) {
+                                files.entry(report.path.clone()).or_insert("edited");
+                            }
+                        }
+                    }
+                    CMD::Run { command, exit_code, .. } => {
+                        commands.push(CommandRun { command: command.clone(), exit_code: *exit_code });
+                    }
+                    CMD::Error { command, message } => errors.push(format!("{}: {}", command, message)),
+                    CMD::ParseError { line, column, message } => {
+                        errors.push(format!("parse error at line {}, column {}: {}", line, column, message));
+                    }
+                    CMD::PathRejected { path, reason } => errors.push(format!("path rejected: {} ({})", path, reason)),
+                    CMD::Callback(message) => callback = Some(message.clone()),
+                    _ => {}
+                }
+            }
+        }
+
+        Report {
+            agent: agent.to_string(),
+            task: task.to_string(),
+            session: None,
+            state,
+            dry_run: false,
+            finished: Local::now().to_rfc3339(),
+            scripts: executed.len(),
+            files: files.into_iter().map(|(path, change)| FileChange { path, change: change.to_string() }).collect(),
+            commands,
+            errors,
+            callback,
+            usage,
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut md = vec![format!("# Report of {}", self.agent), String::new()];
+        md.push(format!("- **State:** {:?}{}", self.state, if self.dry_run { " (dry run, nothing was written)" } else { "" }));
+        if let Some(session) = &self.session {
+            md.push(format!("- **Session:** {}", session));
+        }
+        md.push(format!("- **Finished:** {}", self.finished));
+        md.push(format!("- **Scripts:** {}", self.scripts));
+        md.push(format!(
+            "- **Tokens:** {} ({} in, {} out, {} requests)",
+            self.usage.total(), self.usage.input_tokens, self.usage.output_tokens, self.usage.requests
+        ));
+
+        md.push(String::new());
+        md.push("## Task".to_string());
+        md.push(String::new());
+        md.push(self.task.clone());
+
+        md.push(String::new());
+        md.push("## Files changed".to_string());
+        md.push(String::new());
+        if self.files.is_empty() {
+            md.push("_No files changed._".to_string());
+        } else {
+            md.push("| File | Change |".to_string());
+            md.push("|------|--------|".to_string());
+            md.extend(self.files.iter().map(|f| format!("| `{}` | {} |", f.path, f.change)));
+        }
+
+        md.push(String::new());
+        md.push("## Commands run".to_string());
+        md.push(String::new());
+        if self.commands.is_empty() {
+            md.push("_No commands were run._".to_string());
+        } else {
+            md.push("| Command | Exit code |".to_string());
+            md.push("|---------|-----------|".to_string());
+            md.extend(self.commands.iter().map(|c| format!("| `{}` | {} |", c.command.replace('|', "\\|"), c.exit_code)));
+        }
+
+        if !self.errors.is_empty() {
+            md.push(String::new());
+            md.push("## Errors".to_string());
+            md.push(String::new());
+            md.extend(self.errors.iter().map(|e| format!("- {}", e.lines().next().unwrap_or_default())));
+        }
+
+        md.push(String::new());
+        md.push("## Final callback".to_string());
+        md.push(String::new());
+        md.push(self.callback.clone().unwrap_or_else(|| "_None._".to_string()));
+        md.push(String::new());
+        md.join("\n")
+    }
+
+    /// Write both files into `<employee dir>/report`, the path of the Markdown file
+    pub fn write(&self, employee_dir: &Path, id: &str) -> io::Result<PathBuf> {
+        let dir = employee_dir.join("report");
+        fs::create_dir_all(&dir)?;
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(dir.join(format!("{}.json", id)), json)?;
+
+        let path = dir.join(format!("{}.md", id));
+        fs::write(&path, self.markdown())?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(running: Vec<CMD>) -> Status {
+        Status { running }
+    }
+
+    #[test]
+    fn report_is_written_as_markdown_and_json() {
+        let executed = [
+            status(vec![
+                CMD::CreateFile("a.py".to_string()),
+                CMD::EditFile { path: "a.py".to_string(), line: 1, content: "x = 2".to_string() },
+                CMD::Run { command: "cargo test | tail".to_string(), output: "ok".to_string(), exit_code: 0 },
+                CMD::Callback("tests pass".to_string()),
+            ]),
+            // Rolled back, b.py does not count
+            status(vec![
+                CMD::CreateFile("b.py".to_string()),
+                CMD::Error { command: "EDIT FILE \"c.py\" LINE 9 PUT \"x\"".to_string(), message: "Line 9 does not exist\nmore".to_string() },
+                CMD::RolledBack { paths: vec!["b.py".to_string()] },
+            ]),
+            status(vec![
+                CMD::Patch { path: "c.py".to_string(), outcome: PatchOutcome::NotFound },
+                CMD::EditFile { path: "d.py".to_string(), line: 2, content: "y".to_string() },
+            ]),
+        ];
+        let usage = Usage { requests: 3, input_tokens: 1200, output_tokens: 300 };
+        let mut report = Report::new("Alex", "Fix the tests", SessionState::Finished, &executed, usage);
+        report.session = Some("alex-20250101-120000".to_string());
+
+        let root = std::env::temp_dir().join(format!("orchestra-report-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let path = report.write(&root, "alex-20250101-120000").unwrap();
+        assert_eq!(path, root.join("report/alex-20250101-120000.md"));
+
+        let md = fs::read_to_string(&path).unwrap();
+        for expected in [
+            "# Report of Alex",
+            "- **State:** Finished",
+            "- **Session:** alex-20250101-120000",
+            "- **Scripts:** 3",
+            "- **Tokens:** 1500 (1200 in, 300 out, 3 requests)",
+            "| `a.py` | created |\n| `d.py` | edited |\n\n",
+            "| `cargo test \\| tail` | 0 |",
+            "- EDIT FILE \"c.py\" LINE 9 PUT \"x\": Line 9 does not exist\n",
+            "## Final callback\n\ntests pass\n",
+        ] {
+            assert!(md.contains(expected), "{:?} missing in:\n{}", expected, md);
+        }
+
+        let json: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(root.join("report/alex-20250101-120000.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(json["state"], "finished");
+        assert_eq!(json["files"].as_array().unwrap().len(), 2);
+        assert_eq!(json["commands"][0]["exit_code"], 0);
+        assert_eq!(json["errors"].as_array().unwrap().len(), 1);
+        assert_eq!(json["usage"]["input_tokens"], 1200);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
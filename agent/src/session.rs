@@ -0,0 +1,227 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::coderun::parser::Status;
+use crate::config::Config;
+use crate::error::OrchestraError;
+use crate::git::Worktree;
+use crate::provider::{Message, Usage};
+
+/// Where the agent loop stopped
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionState {
+    /// Working, or the program died in the middle
+    Running,
+    /// No CALLBACK in the last script
+    Finished,
+    /// Operator aborted a script
+    Aborted,
+    /// Model or executor error, can be resumed
+    Failed,
+}
+
+/// Everything needed to continue an agent task, stored as `<employee.dir>/sessions/<id>.json` after each turn
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub agent: String,
+    pub task: String,
+    pub state: SessionState,
+    pub started: String,
+    pub updated: String,
+    pub current_script: usize,
+    pub conversation_history: Vec<Message>,
+    /// Results of every executed script, in order
+    pub executed: Vec<Status>,
+    /// Script received from the model but not executed yet
+    pub pending: Option<String>,
+    /// Checkout the agent works in, None when it works in the project dir
+    #[serde(default)]
+    pub worktree: Option<Worktree>,
+    /// Tokens of every run so far
+    #[serde(default)]
+    pub usage: Usage,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Session {
+    pub fn new(employee_dir: &str, agent: &str, task: &str) -> Self {
+        let name: String = agent
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+            .collect();
+        let now = Local::now();
+        let stamp = format!("{}-{}", name, now.format("%Y%m%d-%H%M%S"));
+
+        // Subtasks of a manager may start within the same second
+        let dir = sessions_dir(employee_dir);
+        let mut id = stamp.clone();
+        let mut n = 1;
+        while dir.join(format!("{}.json", id)).exists() {
+            n += 1;
+            id = format!("{}-{}", stamp, n);
+        }
+
+        Session {
+            path: dir.join(format!("{}.json", id)),
+            id,
+            agent: agent.to_string(),
+            task: task.to_string(),
+            state: SessionState::Running,
+            started: now.to_rfc3339(),
+            updated: now.to_rfc3339(),
+            current_script: 1,
+            conversation_history: Vec::new(),
+            executed: Vec::new(),
+            pending: None,
+            worktree: None,
+            usage: Usage::default(),
+        }
+    }
+
+    /// Find the session among the sessions of every employee
+    pub fn load(config: &Config, id: &str) -> Result<Session, OrchestraError> {
+        if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+            return Err(OrchestraError::Session(format!("\"{}\" is not a session id", id)));
+        }
+        let path = config
+            .employee
+            .iter()
+            .map(|e| sessions_dir(&e.dir).join(format!("{}.json", id)))
+            .find(|p| p.is_file())
+            .ok_or_else(|| OrchestraError::Session(format!("no session \"{}\", see `resume` for the list", id)))?;
+
+        let content = fs::read_to_string(&path)?;
+        let mut session: Session = serde_json::from_str(&content)
+            .map_err(|e| OrchestraError::Session(format!("{}: {}", path.display(), e)))?;
+        session.path = path;
+        Ok(session)
+    }
+
+    /// Write through a temporary file, so a crash never leaves half a session
+    pub fn save(&mut self) -> io::Result<()> {
+        self.updated = Local::now().to_rfc3339();
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn sessions_dir(employee_dir: &str) -> PathBuf {
+    Path::new(employee_dir).join("sessions")
+}
+
+/// One line per stored session of every employee, newest first
+pub fn list(config: &Config) -> Vec<String> {
+    let mut sessions: Vec<Session> = config
+        .employee
+        .iter()
+        .filter_map(|e| fs::read_dir(sessions_dir(&e.dir)).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            serde_json::from_str::<Session>(&fs::read_to_string(&path).ok()?).ok()
+        })
+        .collect();
+    sessions.sort_by(|a, b| b.updated.cmp(&a.updated));
+
+    sessions
+        .iter()
+        .map(|s| {
+            let task: String = s.task.chars().take(60).collect();
+            format!("{} [{:?}] script #{}, {}: {}", s.id, s.state, s.current_script, s.agent, task)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::agent::Agent;
+    use crate::coderun::parser::ExecOptions;
+    use crate::filesystem::Dir;
+    use crate::provider::MockProvider;
+    use super::*;
+
+    /// Temp dir with an employee dir `alex` and an empty `project`, plus a config pointing to them
+    fn setup(test: &str) -> (PathBuf, Config) {
+        let root = std::env::temp_dir().join(format!("orchestra-session-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("project")).unwrap();
+        let config = format!(
+            "manager = []\n[project]\ndir = {:?}\nignore_dir = []\nmax_size = 1024\n\
+            [[employee]]\ndir = {:?}\nname = \"Alex\"\ntask = \"dev\"\n",
+            root.join("project").display().to_string(),
+            root.join("alex").display().to_string(),
+        );
+        (root, toml::from_str(&config).unwrap())
+    }
+
+    #[test]
+    fn saved_session_is_found_by_id() {
+        let (root, config) = setup("load");
+        let mut session = Session::new(&config.employee[0].dir, "Alex Smith", "fix the bug");
+        assert!(session.id.starts_with("alex-smith-"), "{}", session.id);
+        session.conversation_history.push(Message::new("user", "fix the bug"));
+        session.pending = Some("RUN \"ls\"".to_string());
+        session.state = SessionState::Failed;
+        session.save().unwrap();
+
+        let loaded = Session::load(&config, &session.id).unwrap();
+        assert_eq!(loaded.state, SessionState::Failed);
+        assert_eq!(loaded.pending.as_deref(), Some("RUN \"ls\""));
+        assert_eq!(loaded.conversation_history[0].content, "fix the bug");
+        assert!(list(&config)[0].starts_with(&format!("{} [Failed] script #1, Alex Smith", session.id)));
+
+        assert!(Session::load(&config, "../alex/sessions/x").is_err());
+        assert!(Session::load(&config, "nobody-20240101-000000").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn resumed_agent_runs_the_pending_script() {
+        let (root, config) = setup("resume");
+        let mut session = Session::new(&config.employee[0].dir, "Alex", "add b.py");
+        session.conversation_history = vec![
+            Message::new("system", "rules"),
+            Message::new("user", "add b.py"),
+            Message::new("assistant", "CREATE FILE \"b.py\" WITH \"b\""),
+        ];
+        session.pending = Some("CREATE FILE \"b.py\" WITH \"b\"".to_string());
+        session.state = SessionState::Failed;
+        session.save().unwrap();
+
+        // The mock has no answers, the model must not be asked again
+        let session = Session::load(&config, &session.id).unwrap();
+        let id = session.id.clone();
+        let mut agent = Agent::from_session(
+            session,
+            &config.employee[0],
+            Box::new(MockProvider::from_script("", None)),
+            ExecOptions::default(),
+        );
+        let mut dir = Dir::read_from_path(root.join("project")).unwrap();
+        agent.run(&mut dir).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("project/b.py")).unwrap(), "b\n");
+        let finished = Session::load(&config, &id).unwrap();
+        assert_eq!(finished.state, SessionState::Finished);
+        assert!(finished.pending.is_none());
+        assert_eq!(finished.executed.len(), 1);
+        assert_eq!(finished.conversation_history.len(), 3);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}